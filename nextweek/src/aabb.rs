@@ -17,6 +17,7 @@ pub fn surrounding_box(box0: &AABB, box1: &AABB) -> AABB {
 }
 
 #[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct AABB {
     pub min: Vector3<f32>,
     pub max: Vector3<f32>,
@@ -27,6 +28,7 @@ impl AABB {
         AABB { min, max }
     }
 
+    #[allow(dead_code)]
     pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / ray.direction()[a];
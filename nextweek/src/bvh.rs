@@ -2,120 +2,333 @@ use crate::aabb;
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
-use std::cmp::Ordering;
+use nalgebra::Vector3;
 use std::f32;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-enum BVHNode {
-    Branch { left: Box<BVH>, right: Box<BVH> },
-    Leaf(Box<dyn Hittable>),
+const BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to intersecting one primitive.
+const TRAVERSAL_COST: f32 = 0.5;
+// Beyond this depth nodes are split at the median, so that the traversal
+// stack cannot overflow.
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+
+static REPORT: AtomicBool = AtomicBool::new(false);
+
+/// Print the statistics of every BVH built from now on to stderr.
+pub fn report_stats(enabled: bool) {
+    REPORT.store(enabled, Ordering::Relaxed);
 }
 
-pub struct BVH {
-    tree: BVHNode,
+fn surface_area(bbox: &AABB) -> f32 {
+    let d = bbox.max - bbox.min;
+    2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+fn empty_box() -> AABB {
+    AABB::new(Vector3::repeat(f32::MAX), Vector3::repeat(-f32::MAX))
+}
+
+/// Node of the flattened tree. An interior node is followed directly by its
+/// first child and `offset` is the index of the second one; a leaf holds the
+/// `count` primitives starting at `offset`.
+struct Node {
     bbox: AABB,
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+struct Primitive {
+    bbox: AABB,
+    centroid: Vector3<f32>,
+    hittable: Box<dyn Hittable>,
+}
+
+/// Bounding volume hierarchy built with the binned surface area heuristic and
+/// stored as a depth-first array of nodes.
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVH {
+    nodes: Vec<Node>,
+    primitives: Vec<Box<dyn Hittable>>,
 }
 
 impl BVH {
-    pub fn new(mut hittable: Vec<Box<dyn Hittable>>, time0: f32, time1: f32) -> Self {
-        fn box_compare(
-            time0: f32,
-            time1: f32,
-            axis: usize,
-        ) -> impl FnMut(&Box<dyn Hittable>, &Box<dyn Hittable>) -> Ordering {
-            move |a, b| {
-                let a_bbox = a.bounding_box(time0, time1);
-                let b_bbox = b.bounding_box(time0, time1);
-                if let (Some(a), Some(b)) = (a_bbox, b_bbox) {
-                    let ac = a.min[axis] + a.max[axis];
-                    let bc = b.min[axis] + b.max[axis];
-                    ac.partial_cmp(&bc).unwrap()
-                } else {
-                    panic!["no bounding box in bvh node"]
-                }
-            }
+    pub fn new(hittable: Vec<Box<dyn Hittable>>, time0: f32, time1: f32) -> Self {
+        if hittable.is_empty() {
+            panic!["no elements in scene"]
         }
+        let mut primitives: Vec<Primitive> = hittable
+            .into_iter()
+            .map(|hittable| match hittable.bounding_box(time0, time1) {
+                Some(bbox) => Primitive {
+                    bbox,
+                    centroid: 0.5 * (bbox.min + bbox.max),
+                    hittable,
+                },
+                None => panic!["no bounding box in bvh node"],
+            })
+            .collect();
+        let mut nodes = Vec::with_capacity(2 * primitives.len());
+        build(&mut nodes, &mut primitives, 0, 0);
+        let bvh = BVH {
+            nodes,
+            primitives: primitives.into_iter().map(|p| p.hittable).collect(),
+        };
+        if REPORT.load(Ordering::Relaxed) {
+            eprintln!("bvh: {}", bvh.stats());
+        }
+        bvh
+    }
 
-        fn axis_range(
-            hittable: &Vec<Box<dyn Hittable>>,
-            time0: f32,
-            time1: f32,
-            axis: usize,
-        ) -> f32 {
-            let (min, max) = hittable
-                .iter()
-                .fold((f32::MAX, f32::MIN), |(bmin, bmax), hit| {
-                    if let Some(aabb) = hit.bounding_box(time0, time1) {
-                        (bmin.min(aabb.min[axis]), bmax.max(aabb.max[axis]))
-                    } else {
-                        (bmin, bmax)
-                    }
-                });
-            max - min
+    /// Measures the quality of the tree.
+    pub fn stats(&self) -> BVHStats {
+        let root_area = surface_area(&self.nodes[0].bbox);
+        let mut stats = BVHStats {
+            primitives: self.primitives.len(),
+            nodes: self.nodes.len(),
+            leaves: 0,
+            max_depth: 0,
+            min_leaf_size: usize::MAX,
+            max_leaf_size: 0,
+            sah_cost: 0.0,
+        };
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let area = if root_area > 0.0 {
+                surface_area(&node.bbox) / root_area
+            } else {
+                1.0
+            };
+            stats.max_depth = stats.max_depth.max(depth);
+            if node.count > 0 {
+                let count = node.count as usize;
+                stats.leaves += 1;
+                stats.min_leaf_size = stats.min_leaf_size.min(count);
+                stats.max_leaf_size = stats.max_leaf_size.max(count);
+                stats.sah_cost += area * count as f32;
+            } else {
+                stats.sah_cost += area * TRAVERSAL_COST;
+                stack.push((index + 1, depth + 1));
+                stack.push((node.offset as usize, depth + 1));
+            }
         }
+        stats
+    }
+}
 
-        let mut axis_ranges: Vec<(usize, f32)> = (0..3)
-            .map(|a| (a, axis_range(&hittable, time0, time1, a)))
-            .collect();
+/// Shape of a BVH: node counts, depth, leaf sizes and the expected cost of a
+/// ray query under the surface area heuristic, in primitive intersections.
+pub struct BVHStats {
+    pub primitives: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub max_depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub sah_cost: f32,
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} primitives, {} nodes, {} leaves of {}..{} (mean {:.2}), depth {}, SAH cost {:.2}",
+            self.primitives,
+            self.nodes,
+            self.leaves,
+            self.min_leaf_size,
+            self.max_leaf_size,
+            self.primitives as f32 / self.leaves as f32,
+            self.max_depth,
+            self.sah_cost,
+        )
+    }
+}
 
-        axis_ranges.sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+#[derive(Clone, Copy)]
+struct Bin {
+    bbox: AABB,
+    count: usize,
+}
 
-        let axis = axis_ranges[0].0;
+/// Finds the cheapest binned split of `primitives` along any axis, returning
+/// the axis, the bin boundary and the cost relative to the parent's area.
+fn best_split(primitives: &[Primitive], centroids: &AABB) -> Option<(usize, usize, f32)> {
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+        let extent = centroids.max[axis] - centroids.min[axis];
+        if extent <= 0.0 {
+            continue;
+        }
+        let bin_of = |p: &Primitive| {
+            let b = BINS as f32 * (p.centroid[axis] - centroids.min[axis]) / extent;
+            (b as usize).min(BINS - 1)
+        };
+        let mut bins = [Bin {
+            bbox: empty_box(),
+            count: 0,
+        }; BINS];
+        for p in primitives {
+            let bin = &mut bins[bin_of(p)];
+            bin.bbox = aabb::surrounding_box(&bin.bbox, &p.bbox);
+            bin.count += 1;
+        }
+        // Sweep from the right to get the cost of every right-hand side.
+        let mut right_cost = [0.0; BINS];
+        let (mut bbox, mut count) = (empty_box(), 0);
+        for i in (1..BINS).rev() {
+            bbox = aabb::surrounding_box(&bbox, &bins[i].bbox);
+            count += bins[i].count;
+            right_cost[i] = if count > 0 {
+                surface_area(&bbox) * count as f32
+            } else {
+                0.0
+            };
+        }
+        let (mut bbox, mut count) = (empty_box(), 0);
+        for i in 1..BINS {
+            bbox = aabb::surrounding_box(&bbox, &bins[i - 1].bbox);
+            count += bins[i - 1].count;
+            if count == 0 || count == primitives.len() {
+                continue;
+            }
+            let cost = surface_area(&bbox) * count as f32 + right_cost[i];
+            if best.is_none_or(|(_, _, c)| cost < c) {
+                best = Some((axis, i, cost));
+            }
+        }
+    }
+    best
+}
 
-        hittable.sort_unstable_by(box_compare(time0, time1, axis));
-        let len = hittable.len();
-        match len {
-            0 => panic!["no elements in scene"],
-            1 => {
-                let leaf = hittable.pop().unwrap();
-                if let Some(bbox) = leaf.bounding_box(time0, time1) {
-                    BVH {
-                        tree: BVHNode::Leaf(leaf),
-                        bbox,
-                    }
+/// Appends the subtree over `primitives` to `nodes` in depth-first order,
+/// reordering `primitives` so that every leaf covers a contiguous range.
+fn build(nodes: &mut Vec<Node>, primitives: &mut [Primitive], first: usize, depth: usize) -> usize {
+    let bbox = primitives
+        .iter()
+        .fold(empty_box(), |b, p| aabb::surrounding_box(&b, &p.bbox));
+    let centroids = primitives.iter().fold(empty_box(), |b, p| {
+        aabb::surrounding_box(&b, &AABB::new(p.centroid, p.centroid))
+    });
+    let index = nodes.len();
+    nodes.push(Node {
+        bbox,
+        offset: first as u32,
+        count: primitives.len() as u16,
+        axis: 0,
+    });
+    let n = primitives.len();
+    if n == 1 {
+        return index;
+    }
+    let area = surface_area(&bbox);
+    let split = best_split(primitives, &centroids).and_then(|(axis, bin, cost)| {
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + cost / area
+        } else {
+            TRAVERSAL_COST + n as f32
+        };
+        if n <= MAX_LEAF_SIZE && split_cost >= n as f32 {
+            None
+        } else {
+            Some((axis, bin))
+        }
+    });
+    let (axis, mid) = match split {
+        Some((axis, bin)) if depth < MAX_SAH_DEPTH => {
+            let extent = centroids.max[axis] - centroids.min[axis];
+            let (mut left, mut right) = (0, n);
+            // Partition in place around the chosen bin boundary.
+            while left < right {
+                let b =
+                    BINS as f32 * (primitives[left].centroid[axis] - centroids.min[axis]) / extent;
+                if ((b as usize).min(BINS - 1)) < bin {
+                    left += 1;
                 } else {
-                    panic!["no bounding box in bvh node"]
-                }
-            }
-            _ => {
-                let right = BVH::new(hittable.drain(len / 2..).collect(), time0, time1);
-                let left = BVH::new(hittable, time0, time1);
-                let bbox = aabb::surrounding_box(&left.bbox, &right.bbox);
-                BVH {
-                    tree: BVHNode::Branch {
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                    bbox,
+                    right -= 1;
+                    primitives.swap(left, right);
                 }
             }
+            (axis, left)
         }
-    }
+        None if n <= MAX_LEAF_SIZE => return index,
+        _ => {
+            // Coincident centroids or a very deep tree: split at the median,
+            // which keeps the rest of the subtree balanced.
+            let axis = (centroids.max - centroids.min).imax();
+            primitives.select_nth_unstable_by(n / 2, |a, b| {
+                a.centroid[axis].total_cmp(&b.centroid[axis])
+            });
+            (axis, n / 2)
+        }
+    };
+    let (left, right) = primitives.split_at_mut(mid);
+    build(nodes, left, first, depth + 1);
+    let second = build(nodes, right, first + mid, depth + 1);
+    let node = &mut nodes[index];
+    node.offset = second as u32;
+    node.count = 0;
+    node.axis = axis as u8;
+    index
+}
+
+fn hit_box(
+    bbox: &AABB,
+    origin: &Vector3<f32>,
+    inv_dir: &Vector3<f32>,
+    t_min: f32,
+    t_max: f32,
+) -> bool {
+    let t0 = (bbox.min - origin).component_mul(inv_dir);
+    let t1 = (bbox.max - origin).component_mul(inv_dir);
+    let near = t0.inf(&t1).max().max(t_min);
+    let far = t0.sup(&t1).min().min(t_max);
+    near <= far
 }
 
 impl Hittable for BVH {
-    fn hit(&self, ray: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord> {
-        if self.bbox.hit(ray, t_min, t_max) {
-            match &self.tree {
-                BVHNode::Leaf(leaf) => leaf.hit(ray, t_min, t_max),
-                BVHNode::Branch { left, right } => {
-                    let left = left.hit(ray, t_min, t_max);
-                    if let Some(l) = &left {
-                        t_max = l.t
-                    };
-                    let right = right.hit(ray, t_min, t_max);
-                    if right.is_some() {
-                        right
-                    } else {
-                        left
+    fn hit(&self, ray: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord<'_>> {
+        let origin = ray.origin();
+        let inv_dir = ray.direction().map(|d| 1.0 / d);
+        let mut closest = None;
+        let mut stack = [0; STACK_SIZE];
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let index = stack[top];
+            let node = &self.nodes[index];
+            if !hit_box(&node.bbox, &origin, &inv_dir, t_min, t_max) {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.offset as usize;
+                for primitive in &self.primitives[first..first + node.count as usize] {
+                    if let Some(hit) = primitive.hit(ray, t_min, t_max) {
+                        t_max = hit.t;
+                        closest = Some(hit);
                     }
                 }
+            } else {
+                // Visit the child on the near side of the split first.
+                let (near, far) = if inv_dir[node.axis as usize] < 0.0 {
+                    (node.offset as usize, index + 1)
+                } else {
+                    (index + 1, node.offset as usize)
+                };
+                stack[top] = far;
+                stack[top + 1] = near;
+                top += 2;
             }
-        } else {
-            None
         }
+        closest
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        Some(self.bbox)
+        Some(self.nodes[0].bbox)
     }
 }
@@ -1,10 +1,10 @@
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 use std::f32;
 
-fn random_in_unit_disk() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+fn random_in_unit_disk(rng: &mut Sampler) -> Vector3<f32> {
     let unit = Vector3::new(1.0, 1.0, 0.0);
     loop {
         let p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0) - unit;
@@ -27,6 +27,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vector3<f32>,
         look_at: Vector3<f32>,
@@ -57,15 +58,15 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
+    pub fn get_ray(&self, s: f32, t: f32, rng: &mut Sampler) -> Ray {
         let origin = if self.lens_radius == 0.0 {
             self.origin
         } else {
-            let rd = self.lens_radius * random_in_unit_disk();
+            let rd = self.lens_radius * random_in_unit_disk(rng);
             let offset = self.u * rd.x + self.v * rd.y;
             self.origin + offset
         };
-        let time = self.time0 + rand::thread_rng().gen::<f32>() * (self.time1 - self.time0);
+        let time = self.time0 + rng.gen::<f32>() * (self.time1 - self.time0);
         Ray::new(
             origin,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - origin,
@@ -73,3 +74,124 @@ impl Camera {
         )
     }
 }
+
+/// Placement and lens of the camera at one point in time.
+#[derive(Clone, Copy)]
+pub struct CameraKey {
+    pub time: f32,
+    pub look_from: Vector3<f32>,
+    pub look_at: Vector3<f32>,
+    pub vertical_fov: f32,
+    pub focus_dist: f32,
+}
+
+impl CameraKey {
+    fn to_array(self) -> [f32; 8] {
+        let (f, a) = (self.look_from, self.look_at);
+        [
+            f.x,
+            f.y,
+            f.z,
+            a.x,
+            a.y,
+            a.z,
+            self.vertical_fov,
+            self.focus_dist,
+        ]
+    }
+}
+
+/// Camera moving through keyframes along Catmull-Rom splines, which pass
+/// through every key and are parameterized by the key times.
+pub struct CameraPath {
+    keys: Vec<CameraKey>,
+    view_up: Vector3<f32>,
+    aspect: f32,
+    aperture: f32,
+    time0: f32,
+    time1: f32,
+}
+
+impl CameraPath {
+    /// `time0` and `time1` are the shutter interval of a still image. Panics
+    /// if there are no keys.
+    pub fn new(
+        mut keys: Vec<CameraKey>,
+        view_up: Vector3<f32>,
+        aspect: f32,
+        aperture: f32,
+        time0: f32,
+        time1: f32,
+    ) -> Self {
+        assert!(!keys.is_empty(), "camera path without keys");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        CameraPath {
+            keys,
+            view_up,
+            aspect,
+            aperture,
+            time0,
+            time1,
+        }
+    }
+
+    /// Interpolates the keys at `time`, holding the first and last ones
+    /// outside their range.
+    pub fn key(&self, time: f32) -> CameraKey {
+        let k = &self.keys;
+        let i = k.partition_point(|k| k.time <= time);
+        if i == 0 || i == k.len() {
+            return CameraKey {
+                time,
+                ..k[i.min(k.len() - 1)]
+            };
+        }
+        // Tangents of a Catmull-Rom spline with non-uniform knots, one-sided
+        // at the ends of the path.
+        let tangent = |j: usize| {
+            let (a, b) = (j.saturating_sub(1), (j + 1).min(k.len() - 1));
+            let (pa, pb) = (k[a].to_array(), k[b].to_array());
+            let dt = k[b].time - k[a].time;
+            std::array::from_fn::<f32, 8, _>(|n| (pb[n] - pa[n]) / dt)
+        };
+        let (a, b) = (&k[i - 1], &k[i]);
+        let dt = b.time - a.time;
+        let s = (time - a.time) / dt;
+        let (s2, s3) = (s * s, s * s * s);
+        let (h00, h10) = (2.0 * s3 - 3.0 * s2 + 1.0, s3 - 2.0 * s2 + s);
+        let (h01, h11) = (-2.0 * s3 + 3.0 * s2, s3 - s2);
+        let (pa, pb, ma, mb) = (a.to_array(), b.to_array(), tangent(i - 1), tangent(i));
+        let v: [f32; 8] = std::array::from_fn(|n| {
+            h00 * pa[n] + h10 * dt * ma[n] + h01 * pb[n] + h11 * dt * mb[n]
+        });
+        CameraKey {
+            time,
+            look_from: Vector3::new(v[0], v[1], v[2]),
+            look_at: Vector3::new(v[3], v[4], v[5]),
+            vertical_fov: v[6],
+            focus_dist: v[7],
+        }
+    }
+
+    /// Camera for the shutter interval from `time0` to `time1`, placed where
+    /// the path is halfway through it.
+    pub fn camera(&self, time0: f32, time1: f32) -> Camera {
+        let key = self.key(0.5 * (time0 + time1));
+        Camera::new(
+            key.look_from,
+            key.look_at,
+            self.view_up,
+            key.vertical_fov,
+            self.aspect,
+            self.aperture,
+            key.focus_dist,
+            time0,
+            time1,
+        )
+    }
+
+    /// Camera for a still image over the path's own shutter interval.
+    pub fn still(&self) -> Camera {
+        self.camera(self.time0, self.time1)
+    }
+}
@@ -0,0 +1,126 @@
+use crate::aabb;
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+
+// Gap left after a surface before looking for the next one along the ray.
+const STEP: f32 = 0.0001;
+
+#[derive(Clone, Copy)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl Operation {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            Operation::Union => in_a || in_b,
+            Operation::Intersection => in_a && in_b,
+            Operation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// Combines two closed objects with outward normals into one solid. Rays are
+/// traced through the surfaces of both, keeping those where the ray enters or
+/// leaves the result. The walls of a hole cut by a difference are the
+/// surface of `b`, turned inside out, with the material of `b`.
+#[allow(clippy::upper_case_acronyms)]
+pub struct CSG<A: Hittable, B: Hittable> {
+    operation: Operation,
+    a: A,
+    b: B,
+}
+
+#[allow(dead_code)]
+impl<A: Hittable, B: Hittable> CSG<A, B> {
+    pub fn new(operation: Operation, a: A, b: B) -> Self {
+        CSG { operation, a, b }
+    }
+
+    pub fn union(a: A, b: B) -> Self {
+        CSG::new(Operation::Union, a, b)
+    }
+
+    pub fn intersection(a: A, b: B) -> Self {
+        CSG::new(Operation::Intersection, a, b)
+    }
+
+    /// `a` with `b` cut away.
+    pub fn difference(a: A, b: B) -> Self {
+        CSG::new(Operation::Difference, a, b)
+    }
+}
+
+/// Whether the ray passes into the object at `hit`.
+fn entering(ray: &Ray, hit: &HitRecord) -> bool {
+    hit.normal.dot(&ray.direction()) < 0.0
+}
+
+impl<A: Hittable, B: Hittable> Hittable for CSG<A, B> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // Children are traced without the limit of `t_max`, which callers
+        // narrow to the nearest hit so far: the first hit on each decides
+        // whether the ray starts inside it, however far away that hit is.
+        let mut hit_a = self.a.hit(ray, t_min, f32::MAX);
+        let mut hit_b = self.b.hit(ray, t_min, f32::MAX);
+        // The ray starts inside an object if it first meets it leaving.
+        let mut in_a = hit_a.as_ref().is_some_and(|hit| !entering(ray, hit));
+        let mut in_b = hit_b.as_ref().is_some_and(|hit| !entering(ray, hit));
+        loop {
+            let from_a = match (&hit_a, &hit_b) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let was_inside = self.operation.inside(in_a, in_b);
+            let mut hit = if from_a {
+                in_a = !in_a;
+                hit_a.take()
+            } else {
+                in_b = !in_b;
+                hit_b.take()
+            }
+            .unwrap();
+            // Boundaries come in order along the ray, so none is left in range.
+            if hit.t > t_max {
+                return None;
+            }
+            let is_inside = self.operation.inside(in_a, in_b);
+            if was_inside != is_inside {
+                // Face out of the result, which is not always out of the
+                // object that was hit.
+                if entering(ray, &hit) != is_inside {
+                    hit.normal = -hit.normal;
+                    hit.shading_normal = -hit.shading_normal;
+                }
+                return Some(hit);
+            }
+            if from_a {
+                hit_a = self.a.hit(ray, hit.t + STEP, f32::MAX);
+            } else {
+                hit_b = self.b.hit(ray, hit.t + STEP, f32::MAX);
+            }
+        }
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let box_a = self.a.bounding_box(t0, t1);
+        let box_b = self.b.bounding_box(t0, t1);
+        match self.operation {
+            Operation::Union => Some(aabb::surrounding_box(&box_a?, &box_b?)),
+            Operation::Intersection => match (box_a, box_b) {
+                (Some(a), Some(b)) => {
+                    let min = a.min.sup(&b.min);
+                    // Disjoint boxes leave an empty box at the corner.
+                    Some(AABB::new(min, a.max.inf(&b.max).sup(&min)))
+                }
+                (a, b) => a.or(b),
+            },
+            Operation::Difference => box_a,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::render::Tile;
+use nalgebra::Vector3;
+
+/// Radiance samples accumulated for one pixel.
+#[derive(Clone, Copy)]
+pub struct Pixel {
+    pub sum: Vector3<f32>,
+    pub samples: u32,
+}
+
+impl Default for Pixel {
+    fn default() -> Self {
+        Pixel {
+            sum: Vector3::zeros(),
+            samples: 0,
+        }
+    }
+}
+
+impl Pixel {
+    pub fn add_sample(&mut self, color: Vector3<f32>) {
+        self.sum += color;
+        self.samples += 1;
+    }
+
+    pub fn mean(&self) -> Vector3<f32> {
+        if self.samples > 0 {
+            self.sum / self.samples as f32
+        } else {
+            Vector3::zeros()
+        }
+    }
+}
+
+/// Floating-point accumulation buffer. Each pixel keeps the unclamped sum of
+/// its radiance samples and how many samples went into it, rows from the top.
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn tile_rows(&self, tile: &Tile) -> impl Iterator<Item = std::ops::Range<usize>> {
+        let width = self.width as usize;
+        let (x0, x1) = (tile.x0 as usize, tile.x1 as usize);
+        (tile.y0 as usize..tile.y1 as usize).map(move |y| y * width + x0..y * width + x1)
+    }
+
+    /// Copy of the pixels covered by `tile`, rows from the top.
+    pub fn tile(&self, tile: &Tile) -> Vec<Pixel> {
+        self.tile_rows(tile)
+            .flat_map(|row| self.pixels[row].iter().copied())
+            .collect()
+    }
+
+    /// Stores pixels previously taken out with `tile`.
+    pub fn set_tile(&mut self, tile: &Tile, pixels: &[Pixel]) {
+        let rows: Vec<_> = self.tile_rows(tile).collect();
+        for (row, src) in rows.into_iter().zip(pixels.chunks(tile.width() as usize)) {
+            self.pixels[row].copy_from_slice(src);
+        }
+    }
+
+    /// Mean radiance of every pixel, rows from the top.
+    pub fn pixels(&self) -> Vec<Vector3<f32>> {
+        self.pixels.iter().map(Pixel::mean).collect()
+    }
+}
@@ -1,23 +1,54 @@
 use crate::aabb;
 use crate::aabb::AABB;
 use crate::material::Material;
+use crate::medium::Medium;
 use crate::ray::Ray;
 use nalgebra::Vector3;
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub t: f32,
     pub u: f32,
     pub v: f32,
     pub p: Vector3<f32>,
+    /// Geometric normal, which tells the sides of the surface apart.
     pub normal: Vector3<f32>,
+    /// Normal that materials shade with, on the side of `normal`. Differs
+    /// from it where a mesh interpolates vertex normals or a bump map tilts it.
+    pub shading_normal: Vector3<f32>,
+    /// Derivatives of the position by u and v, which span the tangent plane.
+    /// Zero where the surface has no parameterization.
+    pub dpdu: Vector3<f32>,
+    pub dpdv: Vector3<f32>,
     pub material: &'a dyn Material,
+    /// Medium filling the surface on the side opposite the normal.
+    pub medium: Option<&'a Medium>,
+}
+
+impl HitRecord<'_> {
+    /// Whether `direction` is on the same side of the geometric and the
+    /// shading normal. Light going anywhere else would pass through the
+    /// surface.
+    pub fn consistent(&self, direction: &Vector3<f32>) -> bool {
+        self.normal.dot(direction) * self.shading_normal.dot(direction) > 0.0
+    }
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
 }
 
+impl<H: Hittable + ?Sized> Hittable for Box<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+}
+
 #[derive(Default)]
 pub struct HittableList {
     list: Vec<Box<dyn Hittable>>,
@@ -30,7 +61,7 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut closest_so_far = t_max;
         let mut hit_anything: Option<HitRecord> = None;
         for h in self.list.iter() {
@@ -68,9 +99,10 @@ impl<H: Hittable> FlipNormals<H> {
 }
 
 impl<H: Hittable> Hittable for FlipNormals<H> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         self.hittable.hit(ray, t_min, t_max).map(|mut hit| {
             hit.normal = -hit.normal;
+            hit.shading_normal = -hit.shading_normal;
             hit
         })
     }
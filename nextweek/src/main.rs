@@ -1,526 +1,422 @@
 mod aabb;
+mod bump;
 mod bvh;
 mod camera;
+mod csg;
 mod cube;
+mod film;
 mod hittable;
 mod material;
 mod medium;
+mod microfacet;
+mod obj;
+mod onb;
+mod output;
 mod perlin;
+mod ply;
+mod quadric;
 mod ray;
 mod rect;
-mod rotate;
+mod render;
+mod sampler;
+mod scene;
+mod scene_file;
+mod spectrum;
 mod sphere;
 mod texture;
-mod translate;
+mod tonemap;
+mod transform;
+mod triangle;
 
-use crate::bvh::BVH;
 use crate::camera::Camera;
-use crate::cube::Cube;
-use crate::hittable::{FlipNormals, Hittable, HittableList};
-use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
-use crate::medium::ConstantMedium;
+use crate::film::Film;
+use crate::hittable::{HitRecord, Hittable};
+use crate::medium::Media;
 use crate::ray::Ray;
-use crate::rect::{AARect, Plane};
-use crate::rotate::{Axis, Rotate};
-use crate::sphere::{MovingSphere, Sphere};
-use crate::texture::{CheckerTexture, ConstantTexture, ImageTexture, NoiseTexture};
-use crate::translate::Translate;
-use nalgebra::Vector3;
+use crate::render::Renderer;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::spectrum::Spectrum;
+use crate::tonemap::{Operator, ToneMapper};
+use clap::Parser;
+use nalgebra::{Vector3, Vector4};
 use rand::Rng;
-use rayon::prelude::*;
 use std::f32;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
 
-#[allow(dead_code)]
-fn random_scene() -> Box<dyn Hittable> {
-    let mut rng = rand::thread_rng();
-    let origin = Vector3::new(4.0, 0.2, 0.0);
-    let mut world: Vec<Box<dyn Hittable>> = Vec::new();
-    let checker = CheckerTexture::new(
-        ConstantTexture::new(0.2, 0.3, 0.1),
-        ConstantTexture::new(0.9, 0.9, 0.9),
-    );
-    world.push(Box::new(Sphere::new(
-        Vector3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new(checker),
-    )));
-    for a in -10..10 {
-        for b in -10..10 {
-            let choose_material = rng.gen::<f32>();
-            let center = Vector3::new(
-                a as f32 + 0.9 * rng.gen::<f32>(),
-                0.2,
-                b as f32 + 0.9 * rng.gen::<f32>(),
-            );
-            if (center - origin).magnitude() > 0.9 {
-                if choose_material < 0.8 {
-                    // diffuse
-                    world.push(Box::new(MovingSphere::new(
-                        center,
-                        center + Vector3::new(0.0, 0.5 * rng.gen::<f32>(), 0.0),
-                        0.0,
-                        1.0,
-                        0.2,
-                        Lambertian::new(ConstantTexture::new(
-                            rng.gen::<f32>() * rng.gen::<f32>(),
-                            rng.gen::<f32>() * rng.gen::<f32>(),
-                            rng.gen::<f32>() * rng.gen::<f32>(),
-                        )),
-                    )));
-                } else if choose_material < 0.95 {
-                    // metal
-                    world.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        Metal::new(
-                            Vector3::new(
-                                0.5 * (1.0 + rng.gen::<f32>()),
-                                0.5 * (1.0 + rng.gen::<f32>()),
-                                0.5 * (1.0 + rng.gen::<f32>()),
-                            ),
-                            0.5 * rng.gen::<f32>(),
-                        ),
-                    )));
-                } else {
-                    // glass
-                    world.push(Box::new(Sphere::new(center, 0.2, Dielectric::new(1.5))));
-                }
-            }
-        }
+#[derive(Parser)]
+#[command(about = "Ray Tracing: The Next Week")]
+struct Args {
+    /// Scene to render
+    #[arg(short, long, value_enum, default_value = "final")]
+    scene: Scene,
+    /// Scene description file to render instead of a built-in scene
+    #[arg(short = 'f', long, conflicts_with = "scene")]
+    scene_file: Option<PathBuf>,
+    /// Image width in pixels
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+    /// Image height in pixels
+    #[arg(long, default_value_t = 800)]
+    height: u32,
+    /// Samples per pixel
+    #[arg(short = 'n', long, default_value_t = 100)]
+    samples: u32,
+    /// Maximum number of bounces per path
+    #[arg(long, default_value_t = 50)]
+    max_depth: i32,
+    /// Output file (.png, .ppm, .pfm or .exr), binary PPM on stdout if omitted.
+    /// For animations a run of '#' in the name is replaced by the frame number
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Tone mapping operator for 8-bit output
+    #[arg(long, value_enum, default_value = "clamp")]
+    tonemap: Operator,
+    /// Exposure adjustment in stops
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f32,
+    /// Luminance mapped to white by the extended Reinhard operator
+    #[arg(long, default_value_t = 4.0)]
+    white: f32,
+    /// Seed for scene construction and sampling; equal seeds give identical images
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Report the shape and SAH cost of every BVH in the scene
+    #[arg(long)]
+    bvh_stats: bool,
+    /// Edge length of the square render tiles in pixels
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    tile_size: u32,
+    /// Do not report progress on stderr
+    #[arg(short, long)]
+    quiet: bool,
+    /// Number of render threads, all cores if omitted
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+    /// Render the frames FIRST-LAST, or the single frame N, of an animated
+    /// scene file; scene time is in seconds
+    #[arg(long, value_parser = parse_frames, requires_all = ["scene_file", "output"])]
+    frames: Option<(u32, u32)>,
+    /// Frame rate of the animation
+    #[arg(long, default_value_t = 24.0, value_parser = parse_fps)]
+    fps: f32,
+    /// Part of each frame during which the shutter is open, in degrees
+    #[arg(long, default_value_t = 180.0, value_parser = parse_shutter_angle)]
+    shutter_angle: f32,
+    /// Trace paths at sampled wavelengths instead of in RGB, so that glass
+    /// with a dispersive index splits light into colors
+    #[arg(long)]
+    spectral: bool,
+}
+
+fn parse_frames(s: &str) -> Result<(u32, u32), String> {
+    let number = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid frame number '{}'", n))
+    };
+    let (first, last) = match s.split_once('-') {
+        Some((first, last)) => (number(first)?, number(last)?),
+        None => (number(s)?, number(s)?),
+    };
+    if first > last {
+        return Err(format!("frame range {} ends before it starts", s));
     }
-    world.push(Box::new(Sphere::new(
-        Vector3::new(0.0, 1.0, 0.0),
-        1.0,
-        Dielectric::new(1.5),
-    )));
-    world.push(Box::new(Sphere::new(
-        Vector3::new(-4.0, 1.0, 0.0),
-        1.0,
-        Lambertian::new(ConstantTexture::new(0.4, 0.2, 0.1)),
-    )));
-    world.push(Box::new(Sphere::new(
-        Vector3::new(4.0, 1.0, 0.0),
-        1.0,
-        Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0),
-    )));
-    Box::new(BVH::new(world, 0.0, 1.0))
+    Ok((first, last))
 }
 
-#[allow(dead_code)]
-fn two_spheres() -> Box<dyn Hittable> {
-    let checker = CheckerTexture::new(
-        ConstantTexture::new(0.2, 0.3, 0.1),
-        ConstantTexture::new(0.9, 0.9, 0.9),
-    );
-    let mut world = HittableList::default();
-    world.push(Sphere::new(
-        Vector3::new(0.0, -10.0, 0.0),
-        10.0,
-        Lambertian::new(checker.clone()),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(0.0, 10.0, 0.0),
-        10.0,
-        Lambertian::new(checker),
-    ));
-    Box::new(world)
+fn parse_fps(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(fps) if fps > 0.0 && fps.is_finite() => Ok(fps),
+        _ => Err("expected a positive number".to_string()),
+    }
 }
 
-#[allow(dead_code)]
-fn two_perlin_spheres() -> Box<dyn Hittable> {
-    let noise = NoiseTexture::new(4.0);
-    let mut world = HittableList::default();
-    world.push(Sphere::new(
-        Vector3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new(noise.clone()),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(0.0, 2.0, 0.0),
-        2.0,
-        Lambertian::new(noise),
-    ));
-    Box::new(world)
+fn parse_shutter_angle(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(angle) if (0.0..=360.0).contains(&angle) => Ok(angle),
+        _ => Err("expected 0 to 360 degrees".to_string()),
+    }
 }
 
-#[allow(dead_code)]
-fn earth() -> Box<dyn Hittable> {
-    let image = image::open("earthmap.png")
-        .expect("image not found")
-        .to_rgb8();
-    let (nx, ny) = image.dimensions();
-    let data = image.into_raw();
-    let texture = ImageTexture::new(data, nx, ny);
-    let earth = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 2.0, Lambertian::new(texture));
-    Box::new(earth)
+/// Media along `scattered`, which leaves `hit` where `ray` arrived through
+/// `media`. Passing through the surface of a filled object enters or leaves
+/// its medium; the media entered are kept in `inside`.
+fn media_after<'a>(
+    ray: &Ray,
+    hit: &HitRecord<'a>,
+    scattered: &Ray,
+    media: Option<&'a Media<'a>>,
+    inside: &'a mut Option<Media<'a>>,
+) -> Option<&'a Media<'a>> {
+    let side = scattered.direction().dot(&hit.normal);
+    match hit.medium {
+        Some(medium) if ray.direction().dot(&hit.normal) * side > 0.0 => {
+            if side < 0.0 {
+                Some(inside.insert(Media {
+                    medium,
+                    outer: media,
+                }))
+            } else {
+                Media::leave(media, medium)
+            }
+        }
+        _ => media,
+    }
 }
 
-#[allow(dead_code)]
-fn simple_light() -> Box<dyn Hittable> {
-    let noise = NoiseTexture::new(4.0);
-    let mut world = HittableList::default();
-    world.push(Sphere::new(
-        Vector3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new(noise.clone()),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(0.0, 2.0, 0.0),
-        2.0,
-        Lambertian::new(noise),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(0.0, 7.0, 0.0),
-        2.0,
-        DiffuseLight::new(ConstantTexture::new(4.0, 4.0, 4.0)),
-    ));
-    world.push(AARect::new(
-        Plane::XY,
-        3.0,
-        5.0,
-        1.0,
-        3.0,
-        -2.0,
-        DiffuseLight::new(ConstantTexture::new(4.0, 4.0, 4.0)),
-    ));
-    Box::new(world)
+/// Radiance arriving along `ray`, which travels through `media`.
+fn color(
+    ray: &Ray,
+    world: &dyn Hittable,
+    media: Option<&Media>,
+    depth: i32,
+    max_depth: i32,
+    rng: &mut Sampler,
+) -> Vector3<f32> {
+    render::count_ray();
+    let Some(hit) = world.hit(ray, 0.001, f32::MAX) else {
+        return Vector3::zeros();
+    };
+    let mut transmittance = Vector3::repeat(1.0);
+    if let Some(medium) = media.map(|media| media.medium) {
+        let distance = hit.t * ray.direction().norm();
+        let free_path = medium.free_path(rng);
+        if free_path < distance {
+            if depth >= max_depth {
+                return Vector3::zeros();
+            }
+            let (scattered, attenuation) = medium.scatter(ray, free_path, rng);
+            return attenuation.component_mul(&color(
+                &scattered,
+                world,
+                media,
+                depth + 1,
+                max_depth,
+                rng,
+            ));
+        }
+        transmittance = medium.transmittance(distance);
+    }
+    let emitted = hit.material.emitted(hit.u, hit.v, &hit.p);
+    if depth < max_depth {
+        // Paths leaking through a surface with a tilted shading normal end.
+        let scatter = hit.material.scatter(ray, &hit, rng);
+        if let Some((scattered, attenuation)) =
+            scatter.filter(|(scattered, _)| hit.consistent(&scattered.direction()))
+        {
+            let mut inside = None;
+            let media = media_after(ray, &hit, &scattered, media, &mut inside);
+            return transmittance.component_mul(
+                &(emitted
+                    + attenuation.zip_map(
+                        &color(&scattered, world, media, depth + 1, max_depth, rng),
+                        |l, r| l * r,
+                    )),
+            );
+        }
+    }
+    transmittance.component_mul(&emitted)
 }
 
-#[allow(dead_code)]
-fn cornell_box() -> Box<dyn Hittable> {
-    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
-    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
-    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
-    let light = DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0));
-    let mut world = HittableList::default();
-    world.push(FlipNormals::new(AARect::new(
-        Plane::YZ,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        green,
-    )));
-    world.push(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red));
-    world.push(AARect::new(
-        Plane::ZX,
-        227.0,
-        332.0,
-        213.0,
-        343.0,
-        554.0,
-        light,
-    ));
-    world.push(FlipNormals::new(AARect::new(
-        Plane::ZX,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        white.clone(),
-    )));
-    world.push(AARect::new(
-        Plane::ZX,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        0.0,
-        white.clone(),
-    ));
-    world.push(FlipNormals::new(AARect::new(
-        Plane::XY,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        white.clone(),
-    )));
-    world.push(Translate::new(
-        Rotate::new(
-            Axis::Y,
-            Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 165.0, 165.0),
-                white.clone(),
-            ),
-            -18.0,
-        ),
-        Vector3::new(130.0, 0.0, 65.0),
-    ));
-    world.push(Translate::new(
-        Rotate::new(
-            Axis::Y,
-            Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 330.0, 165.0),
-                white,
-            ),
-            15.0,
-        ),
-        Vector3::new(265.0, 0.0, 295.0),
-    ));
-    Box::new(world)
+/// Radiance arriving along `ray` at the wavelengths `lambda`, in nm, with
+/// colors upsampled by `spectrum`. The first wavelength is the hero the
+/// path follows through dispersive materials. Past one, the others are
+/// dropped and the hero stands in for all four, as told by `hero_only`.
+#[allow(clippy::too_many_arguments)]
+fn spectral_color(
+    ray: &Ray,
+    world: &dyn Hittable,
+    media: Option<&Media>,
+    spectrum: &Spectrum,
+    lambda: &Vector4<f32>,
+    hero_only: bool,
+    depth: i32,
+    max_depth: i32,
+    rng: &mut Sampler,
+) -> Vector4<f32> {
+    render::count_ray();
+    let Some(hit) = world.hit(ray, 0.001, f32::MAX) else {
+        return Vector4::zeros();
+    };
+    let mut transmittance = Vector4::repeat(1.0);
+    if let Some(medium) = media.map(|media| media.medium) {
+        let distance = hit.t * ray.direction().norm();
+        let free_path = medium.free_path(rng);
+        if free_path < distance {
+            if depth >= max_depth {
+                return Vector4::zeros();
+            }
+            let (scattered, attenuation) = medium.scatter(ray, free_path, rng);
+            let scattered = scattered.with_wavelength(lambda.x);
+            return spectrum
+                .upsample(&attenuation, lambda)
+                .component_mul(&spectral_color(
+                    &scattered,
+                    world,
+                    media,
+                    spectrum,
+                    lambda,
+                    hero_only,
+                    depth + 1,
+                    max_depth,
+                    rng,
+                ));
+        }
+        transmittance = spectrum.upsample(&medium.transmittance(distance), lambda);
+    }
+    let emitted = spectrum.upsample_emission(&hit.material.emitted(hit.u, hit.v, &hit.p), lambda);
+    if depth < max_depth {
+        // Paths leaking through a surface with a tilted shading normal end.
+        let scatter = hit.material.scatter(ray, &hit, rng);
+        if let Some((scattered, attenuation)) =
+            scatter.filter(|(scattered, _)| hit.consistent(&scattered.direction()))
+        {
+            let scattered = scattered.with_wavelength(lambda.x);
+            let mut inside = None;
+            let media = media_after(ray, &hit, &scattered, media, &mut inside);
+            let split = !hero_only && hit.material.dispersive();
+            let mut attenuation = spectrum.upsample(&attenuation, lambda);
+            if split {
+                attenuation.component_mul_assign(&Vector4::new(4.0, 0.0, 0.0, 0.0));
+            }
+            let incoming = spectral_color(
+                &scattered,
+                world,
+                media,
+                spectrum,
+                lambda,
+                hero_only || split,
+                depth + 1,
+                max_depth,
+                rng,
+            );
+            return transmittance.component_mul(&(emitted + attenuation.component_mul(&incoming)));
+        }
+    }
+    transmittance.component_mul(&emitted)
 }
 
-#[allow(dead_code)]
-fn cornell_smoke() -> Box<dyn Hittable> {
-    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
-    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
-    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
-    let light = DiffuseLight::new(ConstantTexture::new(7.0, 7.0, 7.0));
-    let mut world = HittableList::default();
-    world.push(FlipNormals::new(AARect::new(
-        Plane::YZ,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        green,
-    )));
-    world.push(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red));
-    world.push(AARect::new(
-        Plane::ZX,
-        127.0,
-        432.0,
-        113.0,
-        443.0,
-        554.0,
-        light,
-    ));
-    world.push(FlipNormals::new(AARect::new(
-        Plane::ZX,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        white.clone(),
-    )));
-    world.push(AARect::new(
-        Plane::ZX,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        0.0,
-        white.clone(),
-    ));
-    world.push(FlipNormals::new(AARect::new(
-        Plane::XY,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        white.clone(),
-    )));
-    let box1 = Translate::new(
-        Rotate::new(
-            Axis::Y,
-            Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 165.0, 165.0),
-                white.clone(),
-            ),
-            -18.0,
-        ),
-        Vector3::new(130.0, 0.0, 65.0),
-    );
-    let box2 = Translate::new(
-        Rotate::new(
-            Axis::Y,
-            Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 330.0, 165.0),
-                white,
-            ),
-            15.0,
-        ),
-        Vector3::new(265.0, 0.0, 295.0),
-    );
-    world.push(ConstantMedium::new(
-        box1,
-        0.01,
-        ConstantTexture::new(1.0, 1.0, 1.0),
-    ));
-    world.push(ConstantMedium::new(
-        box2,
-        0.01,
-        ConstantTexture::new(0.0, 0.0, 0.0),
-    ));
-    Box::new(world)
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
 }
 
-fn final_scene() -> Box<dyn Hittable> {
-    let mut rng = rand::thread_rng();
-    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
-    let ground = Lambertian::new(ConstantTexture::new(0.48, 0.83, 0.53));
-    let mut world = HittableList::default();
-    let mut box_list1: Vec<Box<dyn Hittable>> = Vec::new();
-    let nb = 20;
-    for i in 0..nb {
-        for j in 0..20 {
-            let w = 100.0;
-            let x0 = -1000.0 + i as f32 * w;
-            let z0 = -1000.0 + j as f32 * w;
-            let y0 = 0.0;
-            let x1 = x0 + w;
-            let y1 = 100.0 * (rng.gen::<f32>() + 0.01);
-            let z1 = z0 + w;
-            box_list1.push(Box::new(Cube::new(
-                Vector3::new(x0, y0, z0),
-                Vector3::new(x1, y1, z1),
-                ground.clone(),
-            )));
-        }
+fn run(args: Args) -> io::Result<()> {
+    if let Some(path) = &args.output {
+        output::Format::from_path(path)?;
     }
-    world.push(BVH::new(box_list1, 0.0, 1.0));
-    let light = DiffuseLight::new(ConstantTexture::new(7.0, 7.0, 7.0));
-    world.push(AARect::new(
-        Plane::ZX,
-        147.0,
-        412.0,
-        123.0,
-        423.0,
-        554.0,
-        light,
-    ));
-    let center = Vector3::new(400.0, 400.0, 200.0);
-    world.push(MovingSphere::new(
-        center,
-        center + Vector3::new(30.0, 0.0, 0.0),
-        0.0,
-        1.0,
-        50.0,
-        Lambertian::new(ConstantTexture::new(0.7, 0.3, 0.1)),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(260.0, 150.0, 45.0),
-        50.0,
-        Dielectric::new(1.5),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(0.0, 150.0, 145.0),
-        50.0,
-        Metal::new(Vector3::new(0.8, 0.8, 0.9), 10.0),
-    ));
-    let boundary = Sphere::new(
-        Vector3::new(360.0, 150.0, 145.0),
-        70.0,
-        Dielectric::new(1.5),
-    );
-    world.push(boundary.clone());
-    world.push(ConstantMedium::new(
-        boundary,
-        0.2,
-        ConstantTexture::new(0.2, 0.4, 0.9),
-    ));
-    let boundary = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 5000.0, Dielectric::new(1.5));
-    world.push(ConstantMedium::new(
-        boundary,
-        0.0001,
-        ConstantTexture::new(1.0, 1.0, 1.0),
-    ));
-    let image = image::open("earthmap.png")
-        .expect("image not found")
-        .to_rgb8();
-    let (nx, ny) = image.dimensions();
-    let data = image.into_raw();
-    let texture = ImageTexture::new(data, nx, ny);
-    world.push(Sphere::new(
-        Vector3::new(400.0, 200.0, 400.0),
-        100.0,
-        Lambertian::new(texture),
-    ));
-    world.push(Sphere::new(
-        Vector3::new(220.0, 280.0, 300.0),
-        80.0,
-        Lambertian::new(NoiseTexture::new(0.1)),
-    ));
-    let mut box_list2: Vec<Box<dyn Hittable>> = Vec::new();
-    let ns = 1000;
-    for _ in 0..ns {
-        box_list2.push(Box::new(Sphere::new(
-            Vector3::new(
-                165.0 * rng.gen::<f32>(),
-                165.0 * rng.gen::<f32>(),
-                165.0 * rng.gen::<f32>(),
-            ),
-            10.0,
-            white.clone(),
-        )));
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("failed to build thread pool");
+    }
+    bvh::report_stats(args.bvh_stats);
+    let tonemap = ToneMapper::new(args.tonemap, args.exposure, args.white);
+    match args.frames {
+        Some((first, last)) => render_animation(&args, first, last, &tonemap),
+        None => render_still(&args, &tonemap),
     }
-    world.push(Translate::new(
-        Rotate::new(Axis::Y, BVH::new(box_list2, 0.0, 0.1), 15.0),
-        Vector3::new(-100.0, 270.0, 395.0),
-    ));
-    Box::new(world)
 }
 
-fn color(ray: &Ray, world: &Box<dyn Hittable>, depth: i32) -> Vector3<f32> {
-    if let Some(hit) = world.hit(ray, 0.001, f32::MAX) {
-        let emitted = hit.material.emitted(hit.u, hit.v, &hit.p);
-        if depth < 50 {
-            if let Some((scattered, attenuation)) = hit.material.scatter(ray, &hit) {
-                return emitted
-                    + attenuation.zip_map(&color(&scattered, world, depth + 1), |l, r| l * r);
-            }
+fn render_still(args: &Args, tonemap: &ToneMapper) -> io::Result<()> {
+    let aspect = args.width as f32 / args.height as f32;
+    let (world, cam) = match &args.scene_file {
+        Some(path) => {
+            let (world, path) = scene_file::load(path, aspect, args.seed, None)?;
+            (world, path.still())
+        }
+        None => args.scene.build(aspect, args.seed),
+    };
+    let film = render(args, world.as_ref(), &cam, args.seed);
+    match &args.output {
+        Some(path) => output::write_image(path, &film, tonemap),
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            output::write_ppm(&mut out, &film, tonemap)?;
+            out.flush()
         }
-        emitted
-    } else {
-        Vector3::zeros()
     }
 }
 
-fn main() {
-    let nx = 800;
-    let ny = 800;
-    let ns = 100;
-    println!("P3\n{} {}\n255", nx, ny);
-    let world = final_scene();
-    let look_from = Vector3::new(478.0, 278.0, -600.0);
-    let look_at = Vector3::new(278.0, 278.0, 0.0);
-    let focus_dist = 10.0;
-    let aperture = 0.0;
-    let cam = Camera::new(
-        look_from,
-        look_at,
-        Vector3::new(0.0, 1.0, 0.0),
-        40.0,
-        nx as f32 / ny as f32,
-        aperture,
-        focus_dist,
-        0.0,
-        1.0,
+/// Renders frames `first` to `last` of the scene file, frame `n` with the
+/// shutter opening at `n / fps` seconds.
+fn render_animation(args: &Args, first: u32, last: u32, tonemap: &ToneMapper) -> io::Result<()> {
+    let aspect = args.width as f32 / args.height as f32;
+    let frame_time = 1.0 / args.fps;
+    let open_time = args.shutter_angle / 360.0 * frame_time;
+    let bounds = (
+        first as f32 * frame_time,
+        last as f32 * frame_time + open_time,
     );
-    let image = (0..ny)
-        .into_par_iter()
-        .rev()
-        .flat_map(|y| {
-            (0..nx)
-                .flat_map(|x| {
-                    let col: Vector3<f32> = (0..ns)
-                        .map(|_| {
-                            let mut rng = rand::thread_rng();
-                            let u = (x as f32 + rng.gen::<f32>()) / nx as f32;
-                            let v = (y as f32 + rng.gen::<f32>()) / ny as f32;
-                            let ray = cam.get_ray(u, v);
-                            color(&ray, &world, 0)
-                        })
-                        .sum();
-                    col.iter()
-                        .map(|c| (255.99 * (c / ns as f32).sqrt().max(0.0).min(1.0)) as u8)
-                        .collect::<Vec<u8>>()
-                })
-                .collect::<Vec<u8>>()
-        })
-        .collect::<Vec<u8>>();
-    for col in image.chunks(3) {
-        println!("{} {} {}", col[0], col[1], col[2]);
+    // Clap does not enforce the scene file when a built-in scene is named.
+    let scene_file = args.scene_file.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "animations need a scene file given with --scene-file",
+        )
+    })?;
+    let pattern = args.output.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "animations need an output file given with --output",
+        )
+    })?;
+    let (world, path) = scene_file::load(scene_file, aspect, args.seed, Some(bounds))?;
+    for frame in first..=last {
+        let time = frame as f32 * frame_time;
+        let cam = path.camera(time, time + open_time);
+        // A new seed per frame keeps the noise from standing still on screen.
+        let seed = args.seed.wrapping_add(frame as u64);
+        let film = render(args, world.as_ref(), &cam, seed);
+        let file = output::frame_path(pattern, frame);
+        output::write_image(&file, &film, tonemap)?;
+        if !args.quiet {
+            eprintln!("frame {} written to {}", frame, file.display());
+        }
     }
+    Ok(())
+}
+
+fn render(args: &Args, world: &dyn Hittable, cam: &Camera, seed: u64) -> Film {
+    let (nx, ny) = (args.width, args.height);
+    let mut film = Film::new(nx, ny);
+    let renderer = Renderer::new(args.tile_size).progress(!args.quiet);
+    let spectrum = args.spectral.then(Spectrum::new);
+    renderer.render(&mut film, |x, row, pixel| {
+        let y = ny - 1 - row;
+        let index = y as u64 * nx as u64 + x as u64;
+        for s in 0..args.samples {
+            let mut rng = Sampler::new(seed, index, s as u64);
+            let u = (x as f32 + rng.gen::<f32>()) / nx as f32;
+            let v = (y as f32 + rng.gen::<f32>()) / ny as f32;
+            let ray = cam.get_ray(u, v, &mut rng);
+            let sample = match &spectrum {
+                Some(spectrum) => {
+                    let lambda = Spectrum::sample_wavelengths(&mut rng);
+                    let ray = ray.with_wavelength(lambda.x);
+                    let radiance = spectral_color(
+                        &ray,
+                        world,
+                        None,
+                        spectrum,
+                        &lambda,
+                        false,
+                        0,
+                        args.max_depth,
+                        &mut rng,
+                    );
+                    spectrum.to_rgb(&radiance, &lambda)
+                }
+                None => color(&ray, world, None, 0, args.max_depth, &mut rng),
+            };
+            pixel.add_sample(sample);
+        }
+    });
+    film
 }
@@ -1,11 +1,16 @@
 use crate::hittable::HitRecord;
+use crate::microfacet;
+use crate::microfacet::{RoughInterface, GGX};
+use crate::onb::ONB;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::IOR;
 use crate::texture::Texture;
 use nalgebra::Vector3;
 use rand::Rng;
+use std::sync::Arc;
 
-fn random_in_unit_sphere() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+pub fn random_in_unit_sphere(rng: &mut Sampler) -> Vector3<f32> {
     let unit = Vector3::new(1.0, 1.0, 1.0);
     loop {
         let p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) - unit;
@@ -31,15 +36,52 @@ fn refract(v: &Vector3<f32>, n: &Vector3<f32>, ni_over_nt: f32) -> Option<Vector
     }
 }
 
+/// Beer-Lambert transmittance, for the coefficient `absorption`, of the
+/// path inside a dielectric that ends at `hit`, or 1 if the ray arrives from
+/// outside. The path is assumed to start on the same object.
+fn absorbed(absorption: &Vector3<f32>, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+    if ray.direction().dot(&hit.normal) > 0.0 {
+        (-absorption * hit.t * ray.direction().norm()).map(f32::exp)
+    } else {
+        Vector3::repeat(1.0)
+    }
+}
+
 fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
 pub trait Material: Sync {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f32>)>;
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler)
+        -> Option<(Ray, Vector3<f32>)>;
 
     fn emitted(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32>;
+
+    /// Whether scattering depends on the wavelength of the ray, which then
+    /// splits light of different wavelengths apart.
+    fn dispersive(&self) -> bool {
+        false
+    }
+}
+
+impl<M: Material + Send + ?Sized> Material for Arc<M> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        (**self).scatter(ray, hit, rng)
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        (**self).emitted(u, v, p)
+    }
+
+    fn dispersive(&self) -> bool {
+        (**self).dispersive()
+    }
 }
 
 #[derive(Clone)]
@@ -54,8 +96,13 @@ impl<T: Texture> Lambertian<T> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f32>)> {
-        let target = hit.p + hit.normal + random_in_unit_sphere();
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let target = hit.p + hit.shading_normal + random_in_unit_sphere(rng);
         let scattered = Ray::new(hit.p, target - hit.p, ray.time());
         Some((scattered, self.albedo.value(hit.u, hit.v, &hit.p)))
     }
@@ -81,12 +128,17 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f32>)> {
-        let mut reflected = reflect(&ray.direction().normalize(), &hit.normal);
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let mut reflected = reflect(&ray.direction().normalize(), &hit.shading_normal);
         if self.fuzz > 0.0 {
-            reflected += self.fuzz * random_in_unit_sphere()
+            reflected += self.fuzz * random_in_unit_sphere(rng)
         };
-        if reflected.dot(&hit.normal) > 0.0 {
+        if reflected.dot(&hit.shading_normal) > 0.0 {
             let scattered = Ray::new(hit.p, reflected, ray.time());
             Some((scattered, self.albedo))
         } else {
@@ -99,36 +151,127 @@ impl Material for Metal {
     }
 }
 
+/// Rough metal with a GGX microfacet distribution and the Fresnel
+/// reflectance of its complex index of refraction `eta + i k`, given per
+/// color channel.
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Vector3<f32>,
+    k: Vector3<f32>,
+    ggx: GGX,
+}
+
+impl Conductor {
+    /// A `roughness` of 0 gives a mirror.
+    pub fn new(eta: Vector3<f32>, k: Vector3<f32>, roughness: f32) -> Self {
+        Conductor {
+            eta,
+            k,
+            ggx: GGX::from_roughness(roughness),
+        }
+    }
+
+    /// One of `gold`, `silver`, `copper` or `aluminium`, with its index of
+    /// refraction at 650, 550 and 450 nm.
+    pub fn named(name: &str, roughness: f32) -> Option<Self> {
+        let (eta, k) = match name {
+            "gold" => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            "silver" => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            "copper" => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            "aluminium" => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            _ => return None,
+        };
+        Some(Conductor::new(eta.into(), k.into(), roughness))
+    }
+}
+
+impl Material for Conductor {
+    /// Reflects off a microfacet normal sampled from those visible to the
+    /// ray, which leaves the Fresnel term times G2 / G1 as the weight.
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let uvw = ONB::build_from_w(&hit.shading_normal);
+        let wi = uvw.to_local(&-ray.direction().normalize());
+        if wi.z <= 0.0 {
+            return None;
+        }
+        if self.ggx.is_smooth() {
+            let reflected = reflect(&ray.direction(), &hit.shading_normal);
+            let attenuation = microfacet::fresnel_conductor(wi.z, &self.eta, &self.k);
+            return Some((Ray::new(hit.p, reflected, ray.time()), attenuation));
+        }
+        let h = self.ggx.sample_visible(&wi, rng);
+        let wo = microfacet::reflect(&wi, &h);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let attenuation = microfacet::fresnel_conductor(wi.dot(&h), &self.eta, &self.k)
+            * (self.ggx.g(&wi, &wo) / self.ggx.g1(&wi));
+        Some((Ray::new(hit.p, uvw.local(&wo), ray.time()), attenuation))
+    }
+
+    fn emitted(&self, _u: f32, _v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::zeros()
+    }
+}
+
 #[derive(Clone)]
 pub struct Dielectric {
-    ref_idx: f32,
+    ior: IOR,
+    absorption: Vector3<f32>,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f32) -> Self {
-        Dielectric { ref_idx }
+        Dielectric::with_ior(IOR::Constant(ref_idx))
+    }
+
+    /// Glass whose index changes with the wavelength, which splits white
+    /// light into colors in spectral rendering.
+    pub fn with_ior(ior: IOR) -> Self {
+        Dielectric {
+            ior,
+            absorption: Vector3::zeros(),
+        }
+    }
+
+    /// Colors the glass by absorbing light along the way through it, the
+    /// fraction 1 - exp(-absorption d) over a distance d per channel.
+    pub fn absorption(mut self, absorption: Vector3<f32>) -> Self {
+        self.absorption = absorption;
+        self
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f32>)> {
-        let attenuation = Vector3::new(1.0, 1.0, 1.0);
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let attenuation = absorbed(&self.absorption, ray, hit);
+        let ref_idx = self.ior.at(ray.wavelength());
+        let normal = hit.shading_normal;
         let (outward_normal, ni_over_nt, cosine) = if ray.direction().dot(&hit.normal) > 0.0 {
-            let cosine =
-                self.ref_idx * ray.direction().dot(&hit.normal) / ray.direction().magnitude();
-            (-hit.normal, self.ref_idx, cosine)
+            let cosine = ref_idx * ray.direction().dot(&normal) / ray.direction().magnitude();
+            (-normal, ref_idx, cosine)
         } else {
-            let cosine = -ray.direction().dot(&hit.normal) / ray.direction().magnitude();
-            (hit.normal, 1.0 / self.ref_idx, cosine)
+            let cosine = -ray.direction().dot(&normal) / ray.direction().magnitude();
+            (normal, 1.0 / ref_idx, cosine)
         };
         if let Some(refracted) = refract(&ray.direction(), &outward_normal, ni_over_nt) {
-            let reflect_prob = schlick(cosine, self.ref_idx);
-            if rand::thread_rng().gen::<f32>() >= reflect_prob {
+            let reflect_prob = schlick(cosine, ref_idx);
+            if rng.gen::<f32>() >= reflect_prob {
                 let scattered = Ray::new(hit.p, refracted, ray.time());
                 return Some((scattered, attenuation));
             }
         }
-        let reflected = reflect(&ray.direction(), &hit.normal);
+        let reflected = reflect(&ray.direction(), &normal);
         let scattered = Ray::new(hit.p, reflected, ray.time());
         Some((scattered, attenuation))
     }
@@ -136,6 +279,94 @@ impl Material for Dielectric {
     fn emitted(&self, _u: f32, _v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
         Vector3::zeros()
     }
+
+    fn dispersive(&self) -> bool {
+        self.ior.dispersive()
+    }
+}
+
+/// Frosted glass: a dielectric whose surface has GGX microfacets that both
+/// reflect and refract, with exact Fresnel reflectance.
+#[derive(Clone)]
+pub struct RoughDielectric {
+    ggx: GGX,
+    ior: IOR,
+    absorption: Vector3<f32>,
+}
+
+impl RoughDielectric {
+    /// A `roughness` of 0 gives clear glass.
+    #[allow(dead_code)]
+    pub fn new(ref_idx: f32, roughness: f32) -> Self {
+        RoughDielectric::with_ior(IOR::Constant(ref_idx), roughness)
+    }
+
+    /// See `Dielectric::with_ior`.
+    pub fn with_ior(ior: IOR, roughness: f32) -> Self {
+        RoughDielectric {
+            ggx: GGX::from_roughness(roughness),
+            ior,
+            absorption: Vector3::zeros(),
+        }
+    }
+
+    /// See `Dielectric::absorption`.
+    pub fn absorption(mut self, absorption: Vector3<f32>) -> Self {
+        self.absorption = absorption;
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let transmittance = absorbed(&self.absorption, ray, hit);
+        let interface = RoughInterface {
+            ggx: self.ggx,
+            eta: self.ior.at(ray.wavelength()),
+        };
+        if !self.ggx.is_smooth() {
+            let uvw = ONB::build_from_w(&hit.shading_normal);
+            let wi = uvw.to_local(&-ray.direction().normalize());
+            let (wo, weight) = interface.sample(&wi, rng)?;
+            let scattered = Ray::new(hit.p, uvw.local(&wo), ray.time());
+            return Some((scattered, transmittance * weight));
+        }
+        let direction = ray.direction().normalize();
+        let cos_i = -direction.dot(&hit.shading_normal);
+        let eta = interface.eta;
+        let reflectance = microfacet::fresnel_dielectric(cos_i, eta);
+        let (normal, ratio) = if cos_i > 0.0 {
+            (hit.shading_normal, eta)
+        } else {
+            (-hit.shading_normal, 1.0 / eta)
+        };
+        let (direction, attenuation) = match refract(&direction, &normal, 1.0 / ratio) {
+            Some(refracted) if rng.gen::<f32>() >= reflectance => {
+                (refracted, Vector3::repeat(1.0 / (ratio * ratio)))
+            }
+            _ => (
+                reflect(&direction, &hit.shading_normal),
+                Vector3::repeat(1.0),
+            ),
+        };
+        Some((
+            Ray::new(hit.p, direction, ray.time()),
+            transmittance.component_mul(&attenuation),
+        ))
+    }
+
+    fn emitted(&self, _u: f32, _v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::zeros()
+    }
+
+    fn dispersive(&self) -> bool {
+        self.ior.dispersive()
+    }
 }
 
 #[derive(Clone)]
@@ -150,7 +381,12 @@ impl<T: Texture> DiffuseLight<T> {
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
-    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<(Ray, Vector3<f32>)> {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit: &HitRecord,
+        _rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
         None
     }
 
@@ -171,8 +407,13 @@ impl<T: Texture> Isotropic<T> {
 }
 
 impl<T: Texture> Material for Isotropic<T> {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f32>)> {
-        let scattered = Ray::new(hit.p, random_in_unit_sphere(), ray.time());
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let scattered = Ray::new(hit.p, random_in_unit_sphere(rng), ray.time());
         Some((scattered, self.albedo.value(hit.u, hit.v, &hit.p)))
     }
 
@@ -180,3 +421,125 @@ impl<T: Texture> Material for Isotropic<T> {
         Vector3::zeros()
     }
 }
+
+/// Blend of two materials, `first` where the first channel of `mask` is 0
+/// and `second` where it is 1, such as rust over metal. Each path picks one
+/// of them with its share.
+#[derive(Clone)]
+pub struct MixMaterial<A: Material, B: Material, T: Texture> {
+    first: A,
+    second: B,
+    mask: T,
+}
+
+impl<A: Material, B: Material, T: Texture> MixMaterial<A, B, T> {
+    pub fn new(first: A, second: B, mask: T) -> Self {
+        MixMaterial {
+            first,
+            second,
+            mask,
+        }
+    }
+
+    /// Share of `second` at `p`.
+    fn weight(&self, u: f32, v: f32, p: &Vector3<f32>) -> f32 {
+        self.mask.value(u, v, p).x.clamp(0.0, 1.0)
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Material for MixMaterial<A, B, T> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        if rng.gen::<f32>() < self.weight(hit.u, hit.v, &hit.p) {
+            self.second.scatter(ray, hit, rng)
+        } else {
+            self.first.scatter(ray, hit, rng)
+        }
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        self.first
+            .emitted(u, v, p)
+            .lerp(&self.second.emitted(u, v, p), self.weight(u, v, p))
+    }
+
+    fn dispersive(&self) -> bool {
+        self.first.dispersive() || self.second.dispersive()
+    }
+}
+
+/// A clear dielectric coat over `base`, like varnish or the lacquer of car
+/// paint. The coat reflects its Fresnel reflectance off GGX microfacets and
+/// the base gets the rest, losing what the coat reflects back in on the way
+/// out. Light from below sees only the base.
+#[derive(Clone)]
+pub struct Coated<M: Material> {
+    base: M,
+    ior: f32,
+    ggx: GGX,
+}
+
+impl<M: Material> Coated<M> {
+    /// A `roughness` of 0 gives a smooth coat.
+    pub fn new(base: M, ior: f32, roughness: f32) -> Self {
+        Coated {
+            base,
+            ior,
+            ggx: GGX::from_roughness(roughness),
+        }
+    }
+
+    /// Fraction of the light that the coat reflects at the cosine `cos`.
+    fn reflectance(&self, cos: f32) -> f32 {
+        microfacet::fresnel_dielectric(cos.abs(), self.ior)
+    }
+}
+
+impl<M: Material> Material for Coated<M> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        rng: &mut Sampler,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let cos_i = -ray.direction().normalize().dot(&hit.shading_normal);
+        if cos_i <= 0.0 {
+            return self.base.scatter(ray, hit, rng);
+        }
+        // Picking the coat by its reflectance and the base by the rest
+        // cancels the way in.
+        let reflectance = self.reflectance(cos_i);
+        if rng.gen::<f32>() < reflectance {
+            if self.ggx.is_smooth() {
+                let reflected = reflect(&ray.direction(), &hit.shading_normal);
+                return Some((Ray::new(hit.p, reflected, ray.time()), Vector3::repeat(1.0)));
+            }
+            let uvw = ONB::build_from_w(&hit.shading_normal);
+            let wi = uvw.to_local(&-ray.direction().normalize());
+            let h = self.ggx.sample_visible(&wi, rng);
+            let wo = microfacet::reflect(&wi, &h);
+            if wo.z <= 0.0 {
+                return None;
+            }
+            let weight = self.reflectance(wi.dot(&h)) / reflectance * self.ggx.g(&wi, &wo)
+                / self.ggx.g1(&wi);
+            let scattered = Ray::new(hit.p, uvw.local(&wo), ray.time());
+            return Some((scattered, Vector3::repeat(weight)));
+        }
+        let (scattered, attenuation) = self.base.scatter(ray, hit, rng)?;
+        let cos_o = scattered.direction().normalize().dot(&hit.shading_normal);
+        Some((scattered, attenuation * (1.0 - self.reflectance(cos_o))))
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        self.base.emitted(u, v, p)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+}
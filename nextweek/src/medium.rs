@@ -1,7 +1,9 @@
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
+use crate::material;
 use crate::material::Isotropic;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::texture::Texture;
 use nalgebra::Vector3;
 use rand::Rng;
@@ -24,8 +26,8 @@ impl<H: Hittable, T: Texture> ConstantMedium<H, T> {
 }
 
 impl<H: Hittable, T: Texture> Hittable for ConstantMedium<H, T> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut rng = rand::thread_rng();
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut rng = Sampler::from_ray(ray);
         if let Some(mut hit1) = self.boundary.hit(ray, -f32::MAX, f32::MAX) {
             if let Some(mut hit2) = self.boundary.hit(ray, hit1.t + 0.0001, f32::MAX) {
                 if hit1.t < t_min {
@@ -45,7 +47,11 @@ impl<H: Hittable, T: Texture> Hittable for ConstantMedium<H, T> {
                             v: 0.0,
                             p: ray.point_at_parameter(t),
                             normal: Vector3::new(1.0, 0.0, 0.0), // arbitrary
+                            shading_normal: Vector3::new(1.0, 0.0, 0.0),
+                            dpdu: Vector3::zeros(),
+                            dpdv: Vector3::zeros(),
                             material: &self.phase_function,
+                            medium: None,
                         });
                     }
                 }
@@ -58,3 +64,104 @@ impl<H: Hittable, T: Texture> Hittable for ConstantMedium<H, T> {
         self.boundary.bounding_box(t0, t1)
     }
 }
+
+/// Homogeneous medium inside a closed surface, attached to it with `Filled`.
+/// Light through it is absorbed exponentially with distance (Beer-Lambert)
+/// and may scatter off particles in all directions.
+#[derive(Clone)]
+pub struct Medium {
+    absorption: Vector3<f32>,
+    density: f32,
+    albedo: Vector3<f32>,
+}
+
+impl Medium {
+    /// A clear medium absorbing the fraction 1 - exp(-absorption d) over a
+    /// distance d per channel.
+    pub fn new(absorption: Vector3<f32>) -> Self {
+        Medium {
+            absorption,
+            density: 0.0,
+            albedo: Vector3::zeros(),
+        }
+    }
+
+    /// Adds particles that scatter `density` times per unit length on
+    /// average, reflecting the fraction `albedo` of the light.
+    pub fn scattering(mut self, density: f32, albedo: Vector3<f32>) -> Self {
+        self.density = density;
+        self.albedo = albedo;
+        self
+    }
+
+    /// Fraction of the light left after `distance` through the medium.
+    pub fn transmittance(&self, distance: f32) -> Vector3<f32> {
+        (-self.absorption * distance).map(f32::exp)
+    }
+
+    /// Random distance to the next particle, infinite if there are none.
+    pub fn free_path(&self, rng: &mut Sampler) -> f32 {
+        if self.density > 0.0 {
+            -(1.0 / self.density) * rng.gen::<f32>().ln()
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    /// Scatters `ray` off a particle at `distance` along it. Returns the new
+    /// ray and the attenuation, including the absorption on the way.
+    pub fn scatter(&self, ray: &Ray, distance: f32, rng: &mut Sampler) -> (Ray, Vector3<f32>) {
+        let p = ray.point_at_parameter(distance / ray.direction().norm());
+        let scattered = Ray::new(p, material::random_in_unit_sphere(rng), ray.time());
+        (
+            scattered,
+            self.albedo.component_mul(&self.transmittance(distance)),
+        )
+    }
+}
+
+/// Media around a point of a path, innermost first, which lives on the
+/// stack of the integrator.
+#[derive(Clone, Copy)]
+pub struct Media<'a> {
+    pub medium: &'a Medium,
+    pub outer: Option<&'a Media<'a>>,
+}
+
+impl<'a> Media<'a> {
+    /// `media` after passing out of `medium`. Leaving a medium that is not
+    /// the innermost, as where two overlap, changes nothing.
+    pub fn leave(media: Option<&'a Media<'a>>, medium: &Medium) -> Option<&'a Media<'a>> {
+        match media {
+            Some(media) if std::ptr::eq(media.medium, medium) => media.outer,
+            media => media,
+        }
+    }
+}
+
+/// A closed object with outward normals filled with a medium. Rays that
+/// pass through its surface enter or leave the medium, which then also
+/// affects any objects inside.
+pub struct Filled<H: Hittable> {
+    boundary: H,
+    medium: Medium,
+}
+
+impl<H: Hittable> Filled<H> {
+    pub fn new(boundary: H, medium: Medium) -> Self {
+        Filled { boundary, medium }
+    }
+}
+
+impl<H: Hittable> Hittable for Filled<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.boundary.hit(ray, t_min, t_max).map(|mut hit| {
+            hit.medium = Some(&self.medium);
+            hit
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::film::Film;
+use crate::tonemap::ToneMapper;
+use image::{ImageBuffer, ImageFormat, Rgb};
+use nalgebra::Vector3;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Image file formats, picked from the output file extension.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 8-bit PNG.
+    Png,
+    /// Binary 8-bit PPM (P6).
+    Ppm,
+    /// Portable float map, unclamped linear radiance.
+    Pfm,
+    /// OpenEXR, unclamped linear radiance.
+    Exr,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => Ok(Format::Png),
+            Some("ppm") => Ok(Format::Ppm),
+            Some("pfm") => Ok(Format::Pfm),
+            Some("exr") => Ok(Format::Exr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported output format '{}', expected .png, .ppm, .pfm or .exr",
+                    path.display()
+                ),
+            )),
+        }
+    }
+}
+
+fn to_rgb8(pixels: &[Vector3<f32>], tonemap: &ToneMapper) -> Vec<u8> {
+    pixels.iter().flat_map(|p| tonemap.to_srgb8(*p)).collect()
+}
+
+fn to_rgb32f(pixels: &[Vector3<f32>]) -> Vec<f32> {
+    pixels.iter().flat_map(|p| p.iter().copied()).collect()
+}
+
+fn image_error(e: image::ImageError) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Writes a tone mapped binary PPM (P6).
+pub fn write_ppm(out: &mut impl Write, film: &Film, tonemap: &ToneMapper) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", film.width(), film.height())?;
+    out.write_all(&to_rgb8(&film.pixels(), tonemap))
+}
+
+/// Writes the linear radiance as a little-endian PFM, whose rows are stored from the bottom.
+pub fn write_pfm(out: &mut impl Write, film: &Film) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", film.width(), film.height())?;
+    for row in film.pixels().chunks(film.width() as usize).rev() {
+        for c in to_rgb32f(row) {
+            out.write_all(&c.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `film` to `path` in the format given by its extension. 8-bit formats
+/// go through `tonemap`, float formats keep the linear radiance.
+pub fn write_image(path: &Path, film: &Film, tonemap: &ToneMapper) -> io::Result<()> {
+    let (width, height) = (film.width(), film.height());
+    match Format::from_path(path)? {
+        Format::Png => {
+            ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, to_rgb8(&film.pixels(), tonemap))
+                .expect("image size mismatch")
+                .save_with_format(path, ImageFormat::Png)
+                .map_err(image_error)
+        }
+        Format::Exr => {
+            ImageBuffer::<Rgb<f32>, _>::from_raw(width, height, to_rgb32f(&film.pixels()))
+                .expect("image size mismatch")
+                .save_with_format(path, ImageFormat::OpenExr)
+                .map_err(image_error)
+        }
+        Format::Ppm => {
+            let mut out = BufWriter::new(File::create(path)?);
+            write_ppm(&mut out, film, tonemap)?;
+            out.flush()
+        }
+        Format::Pfm => {
+            let mut out = BufWriter::new(File::create(path)?);
+            write_pfm(&mut out, film)?;
+            out.flush()
+        }
+    }
+}
+
+/// File name of frame `frame` of an animation written to `pattern`. A run of
+/// `#` in the file name becomes the frame number padded to its length,
+/// otherwise a four-digit number is added before the extension.
+pub fn frame_path(pattern: &Path, frame: u32) -> PathBuf {
+    let name = pattern
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match name.find('#') {
+        Some(start) => {
+            let len = name[start..].chars().take_while(|&c| c == '#').count();
+            format!(
+                "{}{:0width$}{}",
+                &name[..start],
+                frame,
+                &name[start + len..],
+                width = len
+            )
+        }
+        None => match name.rfind('.') {
+            Some(dot) => format!("{}_{:04}{}", &name[..dot], frame, &name[dot..]),
+            None => format!("{}_{:04}", name, frame),
+        },
+    };
+    pattern.with_file_name(name)
+}
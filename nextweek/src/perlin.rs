@@ -1,8 +1,8 @@
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 
-fn perlin_generate() -> Vec<Vector3<f32>> {
-    let mut rng = rand::thread_rng();
+fn perlin_generate(rng: &mut Sampler) -> Vec<Vector3<f32>> {
     let mut p = Vec::with_capacity(256);
     for _ in 0..256 {
         p.push(
@@ -17,23 +17,23 @@ fn perlin_generate() -> Vec<Vector3<f32>> {
     p
 }
 
-fn permute(p: &mut [usize], n: usize) {
-    let mut rng = rand::thread_rng();
+fn permute(p: &mut [usize], n: usize, rng: &mut Sampler) {
     for i in (0..n).rev() {
         let target = rng.gen_range(0..=i);
         p.swap(i, target);
     }
 }
 
-fn perlin_generate_perm() -> Vec<usize> {
+fn perlin_generate_perm(rng: &mut Sampler) -> Vec<usize> {
     let mut p = Vec::with_capacity(256);
     for i in 0..256 {
         p.push(i);
     }
-    permute(&mut p, 256);
+    permute(&mut p, 256, rng);
     p
 }
 
+#[allow(clippy::needless_range_loop)]
 fn perlin_interp(c: &[[[Vector3<f32>; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
     let uu = u * u * (3.0 - 2.0 * u);
     let vv = v * v * (3.0 - 2.0 * v);
@@ -62,15 +62,16 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut Sampler) -> Self {
         Perlin {
-            ran_vec: perlin_generate(),
-            perm_x: perlin_generate_perm(),
-            perm_y: perlin_generate_perm(),
-            perm_z: perlin_generate_perm(),
+            ran_vec: perlin_generate(rng),
+            perm_x: perlin_generate_perm(rng),
+            perm_y: perlin_generate_perm(rng),
+            perm_z: perlin_generate_perm(rng),
         }
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn noise(&self, p: &Vector3<f32>) -> f32 {
         let u = p.x - f32::floor(p.x);
         let v = p.y - f32::floor(p.y);
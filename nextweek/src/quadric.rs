@@ -0,0 +1,342 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use nalgebra::Vector3;
+use std::f32;
+
+// The shapes stand on `center` with their axis along +y; use a `Transform`
+// to place them otherwise. Normals point out of the solid and u runs around
+// the axis.
+
+/// Hit in the frame of a shape, relative to its center.
+struct LocalHit {
+    t: f32,
+    p: Vector3<f32>,
+    normal: Vector3<f32>,
+    u: f32,
+    v: f32,
+    dpdv: Vector3<f32>,
+}
+
+impl LocalHit {
+    fn closer(self, other: Option<LocalHit>) -> LocalHit {
+        match other {
+            Some(other) if other.t < self.t => other,
+            _ => self,
+        }
+    }
+
+    fn record<'a>(self, center: Vector3<f32>, material: &'a dyn Material) -> HitRecord<'a> {
+        HitRecord {
+            t: self.t,
+            u: self.u,
+            v: self.v,
+            p: self.p + center,
+            normal: self.normal,
+            shading_normal: self.normal,
+            // u turns once around the axis.
+            dpdu: 2.0 * f32::consts::PI * Vector3::new(-self.p.z, 0.0, self.p.x),
+            dpdv: self.dpdv,
+            material,
+            medium: None,
+        }
+    }
+}
+
+/// Angle around the y axis as a fraction of a full turn.
+fn turn(p: &Vector3<f32>) -> f32 {
+    let phi = p.z.atan2(p.x);
+    if phi < 0.0 {
+        (phi + 2.0 * f32::consts::PI) / (2.0 * f32::consts::PI)
+    } else {
+        phi / (2.0 * f32::consts::PI)
+    }
+}
+
+/// Unit vector from the axis towards `p`, or zero on the axis.
+fn radial(p: &Vector3<f32>) -> Vector3<f32> {
+    let r = Vector3::new(p.x, 0.0, p.z);
+    let norm = r.norm();
+    if norm > 0.0 {
+        r / norm
+    } else {
+        Vector3::zeros()
+    }
+}
+
+/// Roots of a t^2 + 2 b t + c in increasing order.
+fn roots(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a.abs() < 1e-12 {
+        return if b != 0.0 {
+            vec![-c / (2.0 * b)]
+        } else {
+            vec![]
+        };
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    let sqrt_discriminant = discriminant.sqrt();
+    let (t0, t1) = ((-b - sqrt_discriminant) / a, (-b + sqrt_discriminant) / a);
+    vec![t0.min(t1), t0.max(t1)]
+}
+
+/// Hit of a disk of `radius` in the plane y = `height`, facing up or down.
+fn hit_disk(
+    o: &Vector3<f32>,
+    d: &Vector3<f32>,
+    height: f32,
+    radius: f32,
+    up: bool,
+    t_min: f32,
+    t_max: f32,
+) -> Option<LocalHit> {
+    let t = (height - o.y) / d.y;
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+    let p = o + t * d;
+    let r = (p.x * p.x + p.z * p.z).sqrt();
+    if r > radius {
+        return None;
+    }
+    let normal = if up { Vector3::y() } else { -Vector3::y() };
+    Some(LocalHit {
+        t,
+        p,
+        normal,
+        u: turn(&p),
+        v: r / radius,
+        dpdv: radius * radial(&p),
+    })
+}
+
+/// First root of a side surface between `t_min` and `t_max` with y within
+/// [0, `height`].
+fn hit_side(
+    o: &Vector3<f32>,
+    d: &Vector3<f32>,
+    (a, b, c): (f32, f32, f32),
+    height: f32,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, Vector3<f32>)> {
+    roots(a, b, c)
+        .into_iter()
+        .filter(|&t| t > t_min && t < t_max)
+        .map(|t| (t, o + t * d))
+        .find(|(_, p)| p.y >= 0.0 && p.y <= height)
+}
+
+fn cylinder_box(center: Vector3<f32>, radius: f32, height: f32) -> AABB {
+    AABB::new(
+        center - Vector3::new(radius, 0.0001, radius),
+        center + Vector3::new(radius, height + 0.0001, radius),
+    )
+}
+
+/// Disk facing +y, with u around the center and v along the radius.
+pub struct Disk<M: Material> {
+    center: Vector3<f32>,
+    radius: f32,
+    material: M,
+}
+
+impl<M: Material> Disk<M> {
+    pub fn new(center: Vector3<f32>, radius: f32, material: M) -> Self {
+        Disk {
+            center,
+            radius,
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Disk<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let o = ray.origin() - self.center;
+        hit_disk(&o, &ray.direction(), 0.0, self.radius, true, t_min, t_max)
+            .map(|hit| hit.record(self.center, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(cylinder_box(self.center, self.radius, 0.0))
+    }
+}
+
+/// Cylinder of `height` above its base center, optionally closed by disks.
+/// On the side v runs up the axis, on the caps along the radius.
+pub struct Cylinder<M: Material> {
+    center: Vector3<f32>,
+    radius: f32,
+    height: f32,
+    capped: bool,
+    material: M,
+}
+
+impl<M: Material> Cylinder<M> {
+    pub fn new(center: Vector3<f32>, radius: f32, height: f32, capped: bool, material: M) -> Self {
+        Cylinder {
+            center,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Cylinder<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = (ray.origin() - self.center, ray.direction());
+        let quadratic = (
+            d.x * d.x + d.z * d.z,
+            o.x * d.x + o.z * d.z,
+            o.x * o.x + o.z * o.z - self.radius.powi(2),
+        );
+        let side = hit_side(&o, &d, quadratic, self.height, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            p,
+            normal: Vector3::new(p.x, 0.0, p.z) / self.radius,
+            u: turn(&p),
+            v: p.y / self.height,
+            dpdv: Vector3::new(0.0, self.height, 0.0),
+        });
+        let mut closest = side;
+        if self.capped {
+            for (height, up) in [(0.0, false), (self.height, true)] {
+                if let Some(cap) = hit_disk(&o, &d, height, self.radius, up, t_min, t_max) {
+                    closest = Some(cap.closer(closest));
+                }
+            }
+        }
+        closest.map(|hit| hit.record(self.center, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(cylinder_box(self.center, self.radius, self.height))
+    }
+}
+
+/// Cone with its base on `center` and its apex `height` above, optionally
+/// closed by a disk at the base. On the side v runs up the axis.
+pub struct Cone<M: Material> {
+    center: Vector3<f32>,
+    radius: f32,
+    height: f32,
+    capped: bool,
+    material: M,
+}
+
+impl<M: Material> Cone<M> {
+    pub fn new(center: Vector3<f32>, radius: f32, height: f32, capped: bool, material: M) -> Self {
+        Cone {
+            center,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Cone<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = (ray.origin() - self.center, ray.direction());
+        // x^2 + z^2 = k^2 (h - y)^2
+        let k2 = (self.radius / self.height).powi(2);
+        let h = self.height - o.y;
+        let quadratic = (
+            d.x * d.x + d.z * d.z - k2 * d.y * d.y,
+            o.x * d.x + o.z * d.z + k2 * h * d.y,
+            o.x * o.x + o.z * o.z - k2 * h * h,
+        );
+        let side = hit_side(&o, &d, quadratic, self.height, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            p,
+            normal: Vector3::new(p.x, k2 * (self.height - p.y), p.z).normalize(),
+            u: turn(&p),
+            v: p.y / self.height,
+            dpdv: Vector3::new(0.0, self.height, 0.0) - self.radius * radial(&p),
+        });
+        let mut closest = side;
+        if self.capped {
+            if let Some(cap) = hit_disk(&o, &d, 0.0, self.radius, false, t_min, t_max) {
+                closest = Some(cap.closer(closest));
+            }
+        }
+        closest.map(|hit| hit.record(self.center, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(cylinder_box(self.center, self.radius, self.height))
+    }
+}
+
+/// Paraboloid y = h (x^2 + z^2) / r^2 opening upwards from its vertex on
+/// `center` to a rim of `radius` at `height`, optionally closed there by a
+/// disk. On the side v runs up the axis.
+pub struct Paraboloid<M: Material> {
+    center: Vector3<f32>,
+    radius: f32,
+    height: f32,
+    capped: bool,
+    material: M,
+}
+
+impl<M: Material> Paraboloid<M> {
+    pub fn new(center: Vector3<f32>, radius: f32, height: f32, capped: bool, material: M) -> Self {
+        Paraboloid {
+            center,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+
+    /// Slope of the profile, dy/dr = `k` r with `k` = 2 h / r^2.
+    fn k(&self) -> f32 {
+        2.0 * self.height / self.radius.powi(2)
+    }
+}
+
+impl<M: Material> Hittable for Paraboloid<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = (ray.origin() - self.center, ray.direction());
+        // k / 2 (x^2 + z^2) - y = 0
+        let a = 0.5 * self.k();
+        let quadratic = (
+            a * (d.x * d.x + d.z * d.z),
+            a * (o.x * d.x + o.z * d.z) - 0.5 * d.y,
+            a * (o.x * o.x + o.z * o.z) - o.y,
+        );
+        let side = hit_side(&o, &d, quadratic, self.height, t_min, t_max).map(|(t, p)| LocalHit {
+            t,
+            p,
+            normal: Vector3::new(2.0 * a * p.x, -1.0, 2.0 * a * p.z).normalize(),
+            u: turn(&p),
+            v: p.y / self.height,
+            // The radius grows as the square root of the height, steeply
+            // from the vertex.
+            dpdv: if p.y > 0.0 {
+                self.height * (Vector3::new(p.x, 0.0, p.z) / (2.0 * p.y) + Vector3::y())
+            } else {
+                Vector3::zeros()
+            },
+        });
+        let mut closest = side;
+        if self.capped {
+            if let Some(cap) = hit_disk(&o, &d, self.height, self.radius, true, t_min, t_max) {
+                closest = Some(cap.closer(closest));
+            }
+        }
+        closest.map(|hit| hit.record(self.center, &self.material))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(cylinder_box(self.center, self.radius, self.height))
+    }
+}
@@ -4,11 +4,24 @@ pub struct Ray {
     a: Vector3<f32>,
     b: Vector3<f32>,
     time: f32,
+    wavelength: Option<f32>,
 }
 
 impl Ray {
     pub fn new(a: Vector3<f32>, b: Vector3<f32>, time: f32) -> Self {
-        Ray { a, b, time }
+        Ray {
+            a,
+            b,
+            time,
+            wavelength: None,
+        }
+    }
+
+    /// The ray carrying light of a single wavelength `lambda` in nm, as
+    /// in spectral rendering.
+    pub fn with_wavelength(mut self, lambda: f32) -> Self {
+        self.wavelength = Some(lambda);
+        self
     }
 
     pub fn origin(&self) -> Vector3<f32> {
@@ -20,6 +33,9 @@ impl Ray {
     pub fn time(&self) -> f32 {
         self.time
     }
+    pub fn wavelength(&self) -> Option<f32> {
+        self.wavelength
+    }
     pub fn point_at_parameter(&self, t: f32) -> Vector3<f32> {
         self.a + t * self.b
     }
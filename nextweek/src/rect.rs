@@ -20,6 +20,14 @@ pub struct AARect<M: Material> {
     material: M,
 }
 
+fn get_axis(plane: &Plane) -> (usize, usize, usize) {
+    match plane {
+        Plane::YZ => (0, 1, 2),
+        Plane::ZX => (1, 2, 0),
+        Plane::XY => (2, 0, 1),
+    }
+}
+
 impl<M: Material> AARect<M> {
     pub fn new(plane: Plane, a0: f32, a1: f32, b0: f32, b1: f32, k: f32, material: M) -> Self {
         AARect {
@@ -35,12 +43,8 @@ impl<M: Material> AARect<M> {
 }
 
 impl<M: Material> Hittable for AARect<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let (k_axis, a_axis, b_axis) = match &self.plane {
-            Plane::YZ => (0, 1, 2),
-            Plane::ZX => (1, 2, 0),
-            Plane::XY => (2, 0, 1),
-        };
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
         let t = (self.k - ray.origin()[k_axis]) / ray.direction()[k_axis];
         if t < t_min || t > t_max {
             None
@@ -55,21 +59,109 @@ impl<M: Material> Hittable for AARect<M> {
                 let p = ray.point_at_parameter(t);
                 let mut normal = Vector3::zeros();
                 normal[k_axis] = 1.0;
+                let mut dpdu = Vector3::zeros();
+                dpdu[a_axis] = self.a1 - self.a0;
+                let mut dpdv = Vector3::zeros();
+                dpdv[b_axis] = self.b1 - self.b0;
                 Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    medium: None,
                 })
             }
         }
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        let min = Vector3::new(self.a0, self.b0, self.k - 0.0001);
-        let max = Vector3::new(self.a1, self.b1, self.k + 0.0001);
+        let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
+        let mut min = Vector3::zeros();
+        let mut max = Vector3::zeros();
+        min[a_axis] = self.a0;
+        max[a_axis] = self.a1;
+        min[b_axis] = self.b0;
+        max[b_axis] = self.b1;
+        min[k_axis] = self.k - 0.0001;
+        max[k_axis] = self.k + 0.0001;
         Some(AABB { min, max })
     }
 }
+
+/// Parallelogram spanned by the edges `u` and `v` from `corner`, facing
+/// along u × v. The texture coordinates run along the two edges.
+pub struct Quad<M: Material> {
+    corner: Vector3<f32>,
+    u: Vector3<f32>,
+    v: Vector3<f32>,
+    normal: Vector3<f32>,
+    // Maps a point in the plane, relative to the corner, to its coordinates
+    // along the edges.
+    w: Vector3<f32>,
+    material: M,
+}
+
+impl<M: Material> Quad<M> {
+    pub fn new(corner: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, material: M) -> Self {
+        let n = u.cross(&v);
+        Quad {
+            corner,
+            u,
+            v,
+            normal: n.normalize(),
+            w: n / n.norm_squared(),
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Quad<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(&ray.direction());
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(self.corner - ray.origin())) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let p = ray.point_at_parameter(t);
+        let planar = p - self.corner;
+        let a = self.w.dot(&planar.cross(&self.v));
+        let b = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+        Some(HitRecord {
+            t,
+            u: a,
+            v: b,
+            p,
+            normal: self.normal,
+            shading_normal: self.normal,
+            dpdu: self.u,
+            dpdv: self.v,
+            material: &self.material,
+            medium: None,
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        let corners = [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        let min = corners.iter().fold(corners[0], |m, c| m.inf(c));
+        let max = corners.iter().fold(corners[0], |m, c| m.sup(c));
+        // Pad the box so it has some thickness when the quad is axis-aligned.
+        let pad = Vector3::repeat(0.0001);
+        Some(AABB::new(min - pad, max + pad))
+    }
+}
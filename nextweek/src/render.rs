@@ -0,0 +1,180 @@
+use crate::film::{Film, Pixel};
+use rayon::prelude::*;
+use std::cell::Cell;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Counts one traced ray on the current thread, for the rays per second report.
+pub fn count_ray() {
+    RAYS.with(|r| r.set(r.get() + 1));
+}
+
+fn rays_traced() -> u64 {
+    RAYS.with(|r| r.get())
+}
+
+/// A rectangular block of the image, `x0..x1` by `y0..y1` with rows from the top.
+#[derive(Clone, Copy)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn area(&self) -> usize {
+        self.width() as usize * self.height() as usize
+    }
+}
+
+/// Cuts the image into `size` x `size` tiles ordered in a spiral from the centre,
+/// so the interesting middle of the frame shows up first.
+pub fn spiral_tiles(width: u32, height: u32, size: u32) -> Vec<Tile> {
+    let nx = width.div_ceil(size) as i64;
+    let ny = height.div_ceil(size) as i64;
+    let (cx, cy) = ((nx - 1) as f32 / 2.0, (ny - 1) as f32 / 2.0);
+    let mut tiles: Vec<(f32, f32, Tile)> = (0..ny)
+        .flat_map(|j| (0..nx).map(move |i| (i, j)))
+        .map(|(i, j)| {
+            let (dx, dy) = (i as f32 - cx, j as f32 - cy);
+            let ring = dx.abs().max(dy.abs()).round();
+            let tile = Tile {
+                x0: i as u32 * size,
+                y0: j as u32 * size,
+                x1: ((i as u32 + 1) * size).min(width),
+                y1: ((j as u32 + 1) * size).min(height),
+            };
+            (ring, dy.atan2(dx), tile)
+        })
+        .collect();
+    tiles.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    tiles.into_iter().map(|(_, _, tile)| tile).collect()
+}
+
+fn format_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+struct Progress {
+    start: Instant,
+    tiles: usize,
+    pixels: usize,
+    report: bool,
+    // tiles done, pixels done, rays traced
+    done: Mutex<(usize, usize, u64)>,
+}
+
+impl Progress {
+    fn new(tiles: usize, pixels: usize, report: bool) -> Self {
+        Progress {
+            start: Instant::now(),
+            tiles,
+            pixels,
+            report,
+            done: Mutex::new((0, 0, 0)),
+        }
+    }
+
+    fn tile_done(&self, pixels: usize, rays: u64) {
+        let mut done = self.done.lock().unwrap();
+        done.0 += 1;
+        done.1 += pixels;
+        done.2 += rays;
+        if self.report {
+            let elapsed = self.start.elapsed();
+            let fraction = done.1 as f64 / self.pixels as f64;
+            let eta = elapsed.mul_f64((1.0 - fraction) / fraction);
+            eprint!(
+                "\rtiles {}/{} ({:.1}%), {:.2} Mrays/s, elapsed {}, ETA {}  ",
+                done.0,
+                self.tiles,
+                100.0 * fraction,
+                done.2 as f64 / elapsed.as_secs_f64() / 1e6,
+                format_duration(elapsed),
+                format_duration(eta),
+            );
+        }
+    }
+
+    fn finish(&self) {
+        if self.report {
+            eprintln!();
+        }
+    }
+}
+
+type TileCallback<'a> = Box<dyn Fn(&Tile, &[Pixel]) + Sync + 'a>;
+
+/// Tile scheduler on top of rayon. Tiles are handed out in spiral order, each
+/// one is rendered into a private buffer by a single thread and then copied
+/// into the film.
+pub struct Renderer<'a> {
+    tile_size: u32,
+    progress: bool,
+    on_tile: Option<TileCallback<'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(tile_size: u32) -> Self {
+        Renderer {
+            tile_size,
+            progress: true,
+            on_tile: None,
+        }
+    }
+
+    /// Turns the progress line on stderr on or off.
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Calls `f` with every finished tile and its pixels, rows from the top.
+    /// It runs on the render threads, so it must be quick and thread safe.
+    #[allow(dead_code)]
+    pub fn on_tile(mut self, f: impl Fn(&Tile, &[Pixel]) + Sync + 'a) -> Self {
+        self.on_tile = Some(Box::new(f));
+        self
+    }
+
+    /// Renders every pixel of `film`. `sample_pixel(x, y, pixel)` adds samples to
+    /// the pixel at column `x` and row `y`, counted from the top left.
+    pub fn render(&self, film: &mut Film, sample_pixel: impl Fn(u32, u32, &mut Pixel) + Sync) {
+        let tiles = spiral_tiles(film.width(), film.height(), self.tile_size);
+        let progress = Progress::new(
+            tiles.len(),
+            film.width() as usize * film.height() as usize,
+            self.progress,
+        );
+        let film = Mutex::new(film);
+        tiles.into_iter().par_bridge().for_each(|tile| {
+            let rays = rays_traced();
+            let mut pixels = film.lock().unwrap().tile(&tile);
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let x = tile.x0 + i as u32 % tile.width();
+                let y = tile.y0 + i as u32 / tile.width();
+                sample_pixel(x, y, pixel);
+            }
+            film.lock().unwrap().set_tile(&tile, &pixels);
+            if let Some(on_tile) = &self.on_tile {
+                on_tile(&tile, &pixels);
+            }
+            progress.tile_done(tile.area(), rays_traced() - rays);
+        });
+        progress.finish();
+    }
+}
@@ -0,0 +1,64 @@
+use crate::ray::Ray;
+use rand::RngCore;
+use rand_pcg::Pcg32;
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Deterministic random number source. A sampler created for a given
+/// (seed, pixel, sample index) always produces the same sequence, so a render
+/// does not depend on which thread traces which sample.
+pub struct Sampler {
+    rng: Pcg32,
+}
+
+impl Sampler {
+    pub fn new(seed: u64, pixel: u64, sample: u64) -> Self {
+        let state = splitmix64(seed ^ splitmix64(pixel ^ splitmix64(sample)));
+        Sampler {
+            rng: Pcg32::new(state, splitmix64(state)),
+        }
+    }
+
+    /// Sampler for scene construction and other work outside the pixel loop.
+    pub fn from_seed(seed: u64) -> Self {
+        Sampler::new(seed, u64::MAX, u64::MAX)
+    }
+
+    /// Sampler derived from the ray itself, for the few places such as
+    /// `Hittable::hit` that have no sampler to draw from. The ray is a
+    /// deterministic function of the sample, so this stays reproducible.
+    pub fn from_ray(ray: &Ray) -> Self {
+        let bits = ray
+            .origin()
+            .iter()
+            .chain(ray.direction().iter())
+            .chain(std::iter::once(&ray.time()))
+            .fold(0, |acc, c| splitmix64(acc ^ c.to_bits() as u64));
+        Sampler {
+            rng: Pcg32::new(bits, splitmix64(bits)),
+        }
+    }
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,528 @@
+use crate::bvh::BVH;
+use crate::camera::Camera;
+use crate::cube::Cube;
+use crate::hittable::{FlipNormals, Hittable, HittableList};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::medium::{ConstantMedium, Filled, Medium};
+use crate::rect::{AARect, Plane};
+use crate::sampler::Sampler;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, ConstantTexture, ImageTexture, NoiseTexture};
+use crate::transform::Transform;
+use clap::ValueEnum;
+use nalgebra::{Matrix4, Vector3};
+use rand::Rng;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Scene {
+    Random,
+    TwoSpheres,
+    TwoPerlinSpheres,
+    Earth,
+    SimpleLight,
+    CornellBox,
+    CornellSmoke,
+    Final,
+}
+
+impl Scene {
+    pub fn build(self, aspect: f32, seed: u64) -> (Box<dyn Hittable>, Camera) {
+        let mut rng = Sampler::from_seed(seed);
+        match self {
+            Scene::Random => random_scene(aspect, &mut rng),
+            Scene::TwoSpheres => two_spheres(aspect),
+            Scene::TwoPerlinSpheres => two_perlin_spheres(aspect, &mut rng),
+            Scene::Earth => earth(aspect),
+            Scene::SimpleLight => simple_light(aspect, &mut rng),
+            Scene::CornellBox => cornell_box(aspect),
+            Scene::CornellSmoke => cornell_smoke(aspect),
+            Scene::Final => final_scene(aspect, &mut rng),
+        }
+    }
+}
+
+fn camera(
+    look_from: Vector3<f32>,
+    look_at: Vector3<f32>,
+    vertical_fov: f32,
+    aspect: f32,
+) -> Camera {
+    let focus_dist = 10.0;
+    let aperture = 0.0;
+    Camera::new(
+        look_from,
+        look_at,
+        Vector3::new(0.0, 1.0, 0.0),
+        vertical_fov,
+        aspect,
+        aperture,
+        focus_dist,
+        0.0,
+        1.0,
+    )
+}
+
+fn random_scene(aspect: f32, rng: &mut Sampler) -> (Box<dyn Hittable>, Camera) {
+    let origin = Vector3::new(4.0, 0.2, 0.0);
+    let mut world: Vec<Box<dyn Hittable>> = Vec::new();
+    let checker = CheckerTexture::new(
+        ConstantTexture::new(0.2, 0.3, 0.1),
+        ConstantTexture::new(0.9, 0.9, 0.9),
+    );
+    world.push(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(checker),
+    )));
+    for a in -10..10 {
+        for b in -10..10 {
+            let choose_material = rng.gen::<f32>();
+            let center = Vector3::new(
+                a as f32 + 0.9 * rng.gen::<f32>(),
+                0.2,
+                b as f32 + 0.9 * rng.gen::<f32>(),
+            );
+            if (center - origin).magnitude() > 0.9 {
+                if choose_material < 0.8 {
+                    // diffuse
+                    world.push(Box::new(MovingSphere::new(
+                        center,
+                        center + Vector3::new(0.0, 0.5 * rng.gen::<f32>(), 0.0),
+                        0.0,
+                        1.0,
+                        0.2,
+                        Lambertian::new(ConstantTexture::new(
+                            rng.gen::<f32>() * rng.gen::<f32>(),
+                            rng.gen::<f32>() * rng.gen::<f32>(),
+                            rng.gen::<f32>() * rng.gen::<f32>(),
+                        )),
+                    )));
+                } else if choose_material < 0.95 {
+                    // metal
+                    world.push(Box::new(Sphere::new(
+                        center,
+                        0.2,
+                        Metal::new(
+                            Vector3::new(
+                                0.5 * (1.0 + rng.gen::<f32>()),
+                                0.5 * (1.0 + rng.gen::<f32>()),
+                                0.5 * (1.0 + rng.gen::<f32>()),
+                            ),
+                            0.5 * rng.gen::<f32>(),
+                        ),
+                    )));
+                } else {
+                    // glass
+                    world.push(Box::new(Sphere::new(center, 0.2, Dielectric::new(1.5))));
+                }
+            }
+        }
+    }
+    world.push(Box::new(Sphere::new(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Dielectric::new(1.5),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Lambertian::new(ConstantTexture::new(0.4, 0.2, 0.1)),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(4.0, 1.0, 0.0),
+        1.0,
+        Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0),
+    )));
+    let cam = camera(
+        Vector3::new(13.0, 2.0, 3.0),
+        Vector3::new(0.0, 0.0, 0.0),
+        20.0,
+        aspect,
+    );
+    (Box::new(BVH::new(world, 0.0, 1.0)), cam)
+}
+
+fn two_spheres(aspect: f32) -> (Box<dyn Hittable>, Camera) {
+    let checker = CheckerTexture::new(
+        ConstantTexture::new(0.2, 0.3, 0.1),
+        ConstantTexture::new(0.9, 0.9, 0.9),
+    );
+    let mut world = HittableList::default();
+    world.push(Sphere::new(
+        Vector3::new(0.0, -10.0, 0.0),
+        10.0,
+        Lambertian::new(checker.clone()),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(0.0, 10.0, 0.0),
+        10.0,
+        Lambertian::new(checker),
+    ));
+    let cam = camera(
+        Vector3::new(13.0, 2.0, 3.0),
+        Vector3::new(0.0, 0.0, 0.0),
+        20.0,
+        aspect,
+    );
+    (Box::new(world), cam)
+}
+
+fn two_perlin_spheres(aspect: f32, rng: &mut Sampler) -> (Box<dyn Hittable>, Camera) {
+    let noise = NoiseTexture::new(4.0, rng);
+    let mut world = HittableList::default();
+    world.push(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(noise.clone()),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(0.0, 2.0, 0.0),
+        2.0,
+        Lambertian::new(noise),
+    ));
+    let cam = camera(
+        Vector3::new(13.0, 2.0, 3.0),
+        Vector3::new(0.0, 0.0, 0.0),
+        20.0,
+        aspect,
+    );
+    (Box::new(world), cam)
+}
+
+fn earth(aspect: f32) -> (Box<dyn Hittable>, Camera) {
+    let image = image::open("earthmap.png")
+        .expect("image not found")
+        .to_rgb8();
+    let (nx, ny) = image.dimensions();
+    let data = image.into_raw();
+    let texture = ImageTexture::new(data, nx, ny);
+    let earth = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 2.0, Lambertian::new(texture));
+    let cam = camera(
+        Vector3::new(13.0, 2.0, 3.0),
+        Vector3::new(0.0, 0.0, 0.0),
+        20.0,
+        aspect,
+    );
+    (Box::new(earth), cam)
+}
+
+fn simple_light(aspect: f32, rng: &mut Sampler) -> (Box<dyn Hittable>, Camera) {
+    let noise = NoiseTexture::new(4.0, rng);
+    let mut world = HittableList::default();
+    world.push(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(noise.clone()),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(0.0, 2.0, 0.0),
+        2.0,
+        Lambertian::new(noise),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(0.0, 7.0, 0.0),
+        2.0,
+        DiffuseLight::new(ConstantTexture::new(4.0, 4.0, 4.0)),
+    ));
+    world.push(AARect::new(
+        Plane::XY,
+        3.0,
+        5.0,
+        1.0,
+        3.0,
+        -2.0,
+        DiffuseLight::new(ConstantTexture::new(4.0, 4.0, 4.0)),
+    ));
+    let cam = camera(
+        Vector3::new(26.0, 3.0, 6.0),
+        Vector3::new(0.0, 2.0, 0.0),
+        20.0,
+        aspect,
+    );
+    (Box::new(world), cam)
+}
+
+fn cornell_box(aspect: f32) -> (Box<dyn Hittable>, Camera) {
+    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0));
+    let mut world = HittableList::default();
+    world.push(FlipNormals::new(AARect::new(
+        Plane::YZ,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        green,
+    )));
+    world.push(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red));
+    world.push(AARect::new(
+        Plane::ZX,
+        227.0,
+        332.0,
+        213.0,
+        343.0,
+        554.0,
+        light,
+    ));
+    world.push(FlipNormals::new(AARect::new(
+        Plane::ZX,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        white.clone(),
+    )));
+    world.push(AARect::new(
+        Plane::ZX,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        white.clone(),
+    ));
+    world.push(FlipNormals::new(AARect::new(
+        Plane::XY,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        white.clone(),
+    )));
+    world.push(
+        Transform::rotate(
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 165.0, 165.0),
+                white.clone(),
+            ),
+            Vector3::y(),
+            -18.0,
+        )
+        .then(Matrix4::new_translation(&Vector3::new(130.0, 0.0, 65.0))),
+    );
+    world.push(
+        Transform::rotate(
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 330.0, 165.0),
+                white,
+            ),
+            Vector3::y(),
+            15.0,
+        )
+        .then(Matrix4::new_translation(&Vector3::new(265.0, 0.0, 295.0))),
+    );
+    let cam = camera(
+        Vector3::new(278.0, 278.0, -800.0),
+        Vector3::new(278.0, 278.0, 0.0),
+        40.0,
+        aspect,
+    );
+    (Box::new(world), cam)
+}
+
+fn cornell_smoke(aspect: f32) -> (Box<dyn Hittable>, Camera) {
+    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(ConstantTexture::new(7.0, 7.0, 7.0));
+    let mut world = HittableList::default();
+    world.push(FlipNormals::new(AARect::new(
+        Plane::YZ,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        green,
+    )));
+    world.push(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red));
+    world.push(AARect::new(
+        Plane::ZX,
+        127.0,
+        432.0,
+        113.0,
+        443.0,
+        554.0,
+        light,
+    ));
+    world.push(FlipNormals::new(AARect::new(
+        Plane::ZX,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        white.clone(),
+    )));
+    world.push(AARect::new(
+        Plane::ZX,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        white.clone(),
+    ));
+    world.push(FlipNormals::new(AARect::new(
+        Plane::XY,
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        white.clone(),
+    )));
+    let box1 = Transform::rotate(
+        Cube::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(165.0, 165.0, 165.0),
+            white.clone(),
+        ),
+        Vector3::y(),
+        -18.0,
+    )
+    .then(Matrix4::new_translation(&Vector3::new(130.0, 0.0, 65.0)));
+    let box2 = Transform::rotate(
+        Cube::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(165.0, 330.0, 165.0),
+            white,
+        ),
+        Vector3::y(),
+        15.0,
+    )
+    .then(Matrix4::new_translation(&Vector3::new(265.0, 0.0, 295.0)));
+    world.push(ConstantMedium::new(
+        box1,
+        0.01,
+        ConstantTexture::new(1.0, 1.0, 1.0),
+    ));
+    world.push(ConstantMedium::new(
+        box2,
+        0.01,
+        ConstantTexture::new(0.0, 0.0, 0.0),
+    ));
+    let cam = camera(
+        Vector3::new(278.0, 278.0, -800.0),
+        Vector3::new(278.0, 278.0, 0.0),
+        40.0,
+        aspect,
+    );
+    (Box::new(world), cam)
+}
+
+fn final_scene(aspect: f32, rng: &mut Sampler) -> (Box<dyn Hittable>, Camera) {
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let ground = Lambertian::new(ConstantTexture::new(0.48, 0.83, 0.53));
+    let mut world = HittableList::default();
+    let mut box_list1: Vec<Box<dyn Hittable>> = Vec::new();
+    let nb = 20;
+    for i in 0..nb {
+        for j in 0..20 {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f32 * w;
+            let z0 = -1000.0 + j as f32 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = 100.0 * (rng.gen::<f32>() + 0.01);
+            let z1 = z0 + w;
+            box_list1.push(Box::new(Cube::new(
+                Vector3::new(x0, y0, z0),
+                Vector3::new(x1, y1, z1),
+                ground.clone(),
+            )));
+        }
+    }
+    world.push(BVH::new(box_list1, 0.0, 1.0));
+    let light = DiffuseLight::new(ConstantTexture::new(7.0, 7.0, 7.0));
+    world.push(AARect::new(
+        Plane::ZX,
+        147.0,
+        412.0,
+        123.0,
+        423.0,
+        554.0,
+        light,
+    ));
+    let center = Vector3::new(400.0, 400.0, 200.0);
+    world.push(MovingSphere::new(
+        center,
+        center + Vector3::new(30.0, 0.0, 0.0),
+        0.0,
+        1.0,
+        50.0,
+        Lambertian::new(ConstantTexture::new(0.7, 0.3, 0.1)),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(260.0, 150.0, 45.0),
+        50.0,
+        Dielectric::new(1.5),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(0.0, 150.0, 145.0),
+        50.0,
+        Metal::new(Vector3::new(0.8, 0.8, 0.9), 10.0),
+    ));
+    world.push(Filled::new(
+        Sphere::new(
+            Vector3::new(360.0, 150.0, 145.0),
+            70.0,
+            Dielectric::new(1.5),
+        ),
+        Medium::new(Vector3::zeros()).scattering(0.2, Vector3::new(0.2, 0.4, 0.9)),
+    ));
+    let boundary = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 5000.0, Dielectric::new(1.5));
+    world.push(ConstantMedium::new(
+        boundary,
+        0.0001,
+        ConstantTexture::new(1.0, 1.0, 1.0),
+    ));
+    let image = image::open("earthmap.png")
+        .expect("image not found")
+        .to_rgb8();
+    let (nx, ny) = image.dimensions();
+    let data = image.into_raw();
+    let texture = ImageTexture::new(data, nx, ny);
+    world.push(Sphere::new(
+        Vector3::new(400.0, 200.0, 400.0),
+        100.0,
+        Lambertian::new(texture),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(220.0, 280.0, 300.0),
+        80.0,
+        Lambertian::new(NoiseTexture::new(0.1, rng)),
+    ));
+    let mut box_list2: Vec<Box<dyn Hittable>> = Vec::new();
+    let ns = 1000;
+    for _ in 0..ns {
+        box_list2.push(Box::new(Sphere::new(
+            Vector3::new(
+                165.0 * rng.gen::<f32>(),
+                165.0 * rng.gen::<f32>(),
+                165.0 * rng.gen::<f32>(),
+            ),
+            10.0,
+            white.clone(),
+        )));
+    }
+    world.push(
+        Transform::rotate(BVH::new(box_list2, 0.0, 0.1), Vector3::y(), 15.0).then(
+            Matrix4::new_translation(&Vector3::new(-100.0, 270.0, 395.0)),
+        ),
+    );
+    let cam = camera(
+        Vector3::new(478.0, 278.0, -600.0),
+        Vector3::new(278.0, 278.0, 0.0),
+        40.0,
+        aspect,
+    );
+    (Box::new(world), cam)
+}
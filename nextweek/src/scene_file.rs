@@ -0,0 +1,1243 @@
+use crate::bump::{Bump, NormalMap};
+use crate::bvh::BVH;
+use crate::camera::{CameraKey, CameraPath};
+use crate::csg::{Operation, CSG};
+use crate::cube::Cube;
+use crate::hittable::{FlipNormals, Hittable, HittableList};
+use crate::material::{
+    Coated, Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal,
+    MixMaterial, RoughDielectric,
+};
+use crate::medium::{ConstantMedium, Filled, Medium};
+use crate::obj;
+use crate::ply;
+use crate::quadric::{Cone, Cylinder, Disk, Paraboloid};
+use crate::rect::{AARect, Plane, Quad};
+use crate::sampler::Sampler;
+use crate::spectrum::IOR;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, ConstantTexture, ImageTexture, NoiseTexture, Texture};
+use crate::transform::{AnimatedTransform, Keyframe, Transform};
+use nalgebra::{Isometry3, Matrix4, Point3, Quaternion, Unit, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+type SharedTexture = Arc<dyn Texture + Send>;
+type SharedMaterial = Arc<dyn Material + Send>;
+
+const HITTABLES: &[&str] = &[
+    "sphere",
+    "moving_sphere",
+    "rect",
+    "quad",
+    "cube",
+    "disk",
+    "cylinder",
+    "cone",
+    "paraboloid",
+    "mesh",
+    "medium",
+    "filled",
+    "translate",
+    "rotate",
+    "scale",
+    "look_at",
+    "transform",
+    "animate",
+    "flip",
+    "union",
+    "intersection",
+    "difference",
+    "list",
+    "bvh",
+];
+
+struct Error {
+    line: usize,
+    message: String,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T> {
+    Err(Error {
+        line,
+        message: message.into(),
+    })
+}
+
+enum Token {
+    Word(String),
+    Number(f32),
+    Str(String),
+    Open,
+    Close,
+    Newline,
+}
+
+fn describe(token: Option<&(usize, Token)>) -> String {
+    match token.map(|(_, t)| t) {
+        Some(Token::Word(w)) => format!("'{}'", w),
+        Some(Token::Number(n)) => format!("number {}", n),
+        Some(Token::Str(s)) => format!("string \"{}\"", s),
+        Some(Token::Open) => "'{'".to_string(),
+        Some(Token::Close) => "'}'".to_string(),
+        Some(Token::Newline) => "end of line".to_string(),
+        None => "end of file".to_string(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let mut chars = text.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c == '#' {
+                break;
+            } else if c == ';' {
+                chars.next();
+                tokens.push((line, Token::Newline));
+            } else if c.is_whitespace() {
+                chars.next();
+            } else if c == '{' || c == '}' {
+                chars.next();
+                tokens.push((line, if c == '{' { Token::Open } else { Token::Close }));
+            } else if c == '"' {
+                chars.next();
+                let end = loop {
+                    match chars.next() {
+                        Some((end, '"')) => break end,
+                        Some(_) => {}
+                        None => return error(line, "unterminated string"),
+                    }
+                };
+                tokens.push((line, Token::Str(text[start + 1..end].to_string())));
+            } else {
+                let mut end = text.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "{};\"#".contains(c) {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                let word = &text[start..end];
+                if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+                    match word.parse() {
+                        Ok(n) => tokens.push((line, Token::Number(n))),
+                        Err(_) => return error(line, format!("invalid number '{}'", word)),
+                    }
+                } else if c.is_alphabetic() || c == '_' {
+                    tokens.push((line, Token::Word(word.to_string())));
+                } else {
+                    return error(line, format!("unexpected character '{}'", c));
+                }
+            }
+        }
+        tokens.push((line, Token::Newline));
+    }
+    Ok(tokens)
+}
+
+enum Atom {
+    Word(String),
+    Number(f32),
+    Str(String),
+}
+
+/// One line of the file: a key, its arguments and an optional nested block.
+struct Entry {
+    line: usize,
+    key: String,
+    args: Vec<Atom>,
+    block: Option<Vec<Entry>>,
+}
+
+fn parse_entries(
+    tokens: &[(usize, Token)],
+    pos: &mut usize,
+    open_line: Option<usize>,
+) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    loop {
+        while let Some((_, Token::Newline)) = tokens.get(*pos) {
+            *pos += 1;
+        }
+        let (line, key) = match tokens.get(*pos) {
+            None => match open_line {
+                Some(open) => return error(open, "'{' is never closed"),
+                None => return Ok(entries),
+            },
+            Some((line, Token::Close)) => match open_line {
+                Some(_) => {
+                    *pos += 1;
+                    return Ok(entries);
+                }
+                None => return error(*line, "unmatched '}'"),
+            },
+            Some((line, Token::Word(key))) => (*line, key.clone()),
+            Some((line, _)) => {
+                return error(
+                    *line,
+                    format!("expected a name, found {}", describe(tokens.get(*pos))),
+                )
+            }
+        };
+        *pos += 1;
+        let mut args = Vec::new();
+        let mut block = None;
+        loop {
+            match tokens.get(*pos) {
+                Some((_, Token::Word(w))) => args.push(Atom::Word(w.clone())),
+                Some((_, Token::Number(n))) => args.push(Atom::Number(*n)),
+                Some((_, Token::Str(s))) => args.push(Atom::Str(s.clone())),
+                Some((line, Token::Open)) => {
+                    *pos += 1;
+                    block = Some(parse_entries(tokens, pos, Some(*line))?);
+                    match tokens.get(*pos) {
+                        Some((_, Token::Newline | Token::Close)) | None => break,
+                        Some((line, _)) => {
+                            return error(*line, "expected end of line after '}'");
+                        }
+                    }
+                }
+                _ => break,
+            }
+            *pos += 1;
+        }
+        entries.push(Entry {
+            line,
+            key,
+            args,
+            block,
+        });
+    }
+}
+
+/// Arguments and block that describe a texture or material, e.g. the
+/// `constant 1 0 0` in `albedo constant 1 0 0`.
+struct Spec<'e> {
+    line: usize,
+    args: &'e [Atom],
+    block: Option<&'e [Entry]>,
+}
+
+impl<'e> Spec<'e> {
+    fn kind(&self, what: &str) -> Result<&'e str> {
+        match self.args.first() {
+            Some(Atom::Word(w)) => Ok(w),
+            _ => error(self.line, format!("expected a {}", what)),
+        }
+    }
+
+    fn rest(&self) -> Spec<'e> {
+        Spec {
+            line: self.line,
+            args: &self.args[1..],
+            block: self.block,
+        }
+    }
+
+    fn numbers<const N: usize>(&self, what: &str) -> Result<[f32; N]> {
+        if self.block.is_some() {
+            return error(self.line, format!("{} does not take a block", what));
+        }
+        let numbers: Vec<f32> = self
+            .args
+            .iter()
+            .filter_map(|a| match a {
+                Atom::Number(n) => Some(*n),
+                _ => None,
+            })
+            .collect();
+        match numbers.try_into() {
+            Ok(numbers) if self.args.len() == N => Ok(numbers),
+            _ if N == 1 => error(self.line, format!("{} expects a number", what)),
+            _ => error(self.line, format!("{} expects {} numbers", what, N)),
+        }
+    }
+
+    fn vector(&self, what: &str) -> Result<Vector3<f32>> {
+        let [x, y, z] = self.numbers(what)?;
+        Ok(Vector3::new(x, y, z))
+    }
+
+    fn number(&self, what: &str) -> Result<f32> {
+        let [n] = self.numbers(what)?;
+        Ok(n)
+    }
+
+    fn word(&self, what: &str) -> Result<&'e str> {
+        match (self.args, self.block) {
+            ([Atom::Word(w)], None) => Ok(w),
+            _ => error(self.line, format!("{} expects a single name", what)),
+        }
+    }
+
+    fn flag(&self, what: &str) -> Result<()> {
+        match (self.args, self.block) {
+            ([], None) => Ok(()),
+            _ => error(self.line, format!("{} takes no arguments", what)),
+        }
+    }
+
+    fn string(&self, what: &str) -> Result<&'e str> {
+        match (self.args, self.block) {
+            ([Atom::Str(s)], None) => Ok(s),
+            _ => error(self.line, format!("{} expects a quoted string", what)),
+        }
+    }
+}
+
+impl Entry {
+    fn spec(&self) -> Spec<'_> {
+        Spec {
+            line: self.line,
+            args: &self.args,
+            block: self.block.as_deref(),
+        }
+    }
+}
+
+/// Properties and child objects found in the block of an object.
+struct Block<'e> {
+    line: usize,
+    kind: &'e str,
+    properties: HashMap<&'e str, &'e Entry>,
+    repeated: Vec<&'e Entry>,
+    children: Vec<&'e Entry>,
+}
+
+impl<'e> Block<'e> {
+    fn new(entry: &'e Entry, allowed: &[&str]) -> Result<Self> {
+        Block::with_repeated(entry, allowed, &[])
+    }
+
+    /// Like `new`, but the properties in `repeated` may be given any number
+    /// of times.
+    fn with_repeated(entry: &'e Entry, allowed: &[&str], repeated: &[&str]) -> Result<Self> {
+        if !entry.args.is_empty() {
+            return error(entry.line, format!("'{}' takes no arguments", entry.key));
+        }
+        Block::parse(
+            entry.line,
+            &entry.key,
+            entry.block.as_deref(),
+            allowed,
+            repeated,
+        )
+    }
+
+    fn parse(
+        line: usize,
+        kind: &'e str,
+        entries: Option<&'e [Entry]>,
+        allowed: &[&str],
+        repeated: &[&str],
+    ) -> Result<Self> {
+        let Some(entries) = entries else {
+            return error(line, format!("'{}' needs a {{ }} block", kind));
+        };
+        let mut block = Block {
+            line,
+            kind,
+            properties: HashMap::new(),
+            repeated: Vec::new(),
+            children: Vec::new(),
+        };
+        for e in entries {
+            if repeated.contains(&e.key.as_str()) {
+                block.repeated.push(e);
+            } else if allowed.contains(&e.key.as_str()) {
+                if block.properties.insert(&e.key, e).is_some() {
+                    return error(e.line, format!("'{}' is given twice", e.key));
+                }
+            } else if HITTABLES.contains(&e.key.as_str()) {
+                block.children.push(e);
+            } else {
+                return error(
+                    e.line,
+                    format!("unknown property '{}' in '{}'", e.key, kind),
+                );
+            }
+        }
+        Ok(block)
+    }
+
+    fn optional(&self, key: &str) -> Option<Spec<'e>> {
+        self.properties.get(key).map(|e| e.spec())
+    }
+
+    fn required(&self, key: &str) -> Result<Spec<'e>> {
+        match self.optional(key) {
+            Some(spec) => Ok(spec),
+            None => error(self.line, format!("'{}' is missing '{}'", self.kind, key)),
+        }
+    }
+
+    /// Every occurrence of a repeated property, in order.
+    fn all(&self, key: &str) -> Vec<&'e Entry> {
+        self.repeated
+            .iter()
+            .copied()
+            .filter(|e| e.key == key)
+            .collect()
+    }
+
+    fn no_children(&self) -> Result<()> {
+        match self.children.first() {
+            Some(child) => error(
+                child.line,
+                format!("'{}' cannot contain '{}'", self.kind, child.key),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn two_children(&self) -> Result<(&'e Entry, &'e Entry)> {
+        match self.children.as_slice() {
+            [a, b] => Ok((a, b)),
+            _ => error(
+                self.line,
+                format!("'{}' must contain exactly two objects", self.kind),
+            ),
+        }
+    }
+
+    fn only_child(&self) -> Result<&'e Entry> {
+        match self.children.as_slice() {
+            [child] => Ok(child),
+            _ => error(
+                self.line,
+                format!("'{}' must contain exactly one object", self.kind),
+            ),
+        }
+    }
+}
+
+struct Loader<'a> {
+    dir: &'a Path,
+    rng: Sampler,
+    time0: f32,
+    time1: f32,
+    // Interval the bounding boxes of moving objects must cover.
+    bounds: (f32, f32),
+    textures: HashMap<String, SharedTexture>,
+    materials: HashMap<String, SharedMaterial>,
+}
+
+impl<'a> Loader<'a> {
+    fn camera(&self, entry: &Entry, aspect: f32) -> Result<CameraPath> {
+        let block = Block::with_repeated(
+            entry,
+            &[
+                "look_from",
+                "look_at",
+                "up",
+                "fov",
+                "aperture",
+                "focus_dist",
+                "time",
+            ],
+            &["key"],
+        )?;
+        block.no_children()?;
+        let up = match block.optional("up") {
+            Some(spec) => spec.vector("up")?,
+            None => Vector3::new(0.0, 1.0, 0.0),
+        };
+        let aperture = match block.optional("aperture") {
+            Some(spec) => spec.number("aperture")?,
+            None => 0.0,
+        };
+        let fov = match block.optional("fov") {
+            Some(spec) => Some(spec.number("fov")?),
+            None => None,
+        };
+        let focus_dist = match block.optional("focus_dist") {
+            Some(spec) => spec.number("focus_dist")?,
+            None => 10.0,
+        };
+        let keys = block.all("key");
+        let keys = if keys.is_empty() {
+            vec![CameraKey {
+                time: self.time0,
+                look_from: block.required("look_from")?.vector("look_from")?,
+                look_at: block.required("look_at")?.vector("look_at")?,
+                vertical_fov: block.required("fov")?.number("fov")?,
+                focus_dist,
+            }]
+        } else {
+            for key in ["look_from", "look_at"] {
+                if let Some(spec) = block.optional(key) {
+                    return error(
+                        spec.line,
+                        format!("'{}' belongs in the keys of an animated camera", key),
+                    );
+                }
+            }
+            let mut camera_keys = Vec::new();
+            for key in keys {
+                // Keys inherit the field of view and focus distance of the
+                // camera unless they set their own.
+                let block =
+                    Block::new(key, &["time", "look_from", "look_at", "fov", "focus_dist"])?;
+                block.no_children()?;
+                camera_keys.push(CameraKey {
+                    time: block.required("time")?.number("time")?,
+                    look_from: block.required("look_from")?.vector("look_from")?,
+                    look_at: block.required("look_at")?.vector("look_at")?,
+                    vertical_fov: match (block.optional("fov"), fov) {
+                        (Some(spec), _) => spec.number("fov")?,
+                        (None, Some(fov)) => fov,
+                        (None, None) => return error(key.line, "'key' is missing 'fov'"),
+                    },
+                    focus_dist: match block.optional("focus_dist") {
+                        Some(spec) => spec.number("focus_dist")?,
+                        None => focus_dist,
+                    },
+                });
+            }
+            camera_keys
+        };
+        Ok(CameraPath::new(
+            keys, up, aspect, aperture, self.time0, self.time1,
+        ))
+    }
+
+    fn texture(&mut self, spec: Spec) -> Result<SharedTexture> {
+        if let [Atom::Number(_), ..] = spec.args {
+            let [r, g, b] = spec.numbers("color")?;
+            return Ok(Arc::new(ConstantTexture::new(r, g, b)));
+        }
+        let kind = spec.kind("texture")?;
+        let rest = spec.rest();
+        Ok(match kind {
+            "constant" => {
+                let [r, g, b] = rest.numbers("constant")?;
+                Arc::new(ConstantTexture::new(r, g, b))
+            }
+            "checker" => {
+                if !rest.args.is_empty() {
+                    return error(spec.line, "'checker' takes no arguments");
+                }
+                let block = Block::parse(spec.line, kind, rest.block, &["odd", "even"], &[])?;
+                block.no_children()?;
+                let odd = self.texture(block.required("odd")?)?;
+                let even = self.texture(block.required("even")?)?;
+                Arc::new(CheckerTexture::new(odd, even))
+            }
+            "noise" => Arc::new(NoiseTexture::new(rest.number("noise")?, &mut self.rng)),
+            "image" => {
+                let path = self.dir.join(rest.string("image")?);
+                let image = match image::open(&path) {
+                    Ok(image) => image.to_rgb8(),
+                    Err(e) => {
+                        return error(
+                            spec.line,
+                            format!("cannot load image '{}': {}", path.display(), e),
+                        )
+                    }
+                };
+                let (nx, ny) = image.dimensions();
+                Arc::new(ImageTexture::new(image.into_raw(), nx, ny))
+            }
+            name => {
+                let texture = spec.word("a texture name")?;
+                match self.textures.get(texture) {
+                    Some(texture) => texture.clone(),
+                    None => return error(spec.line, format!("unknown texture '{}'", name)),
+                }
+            }
+        })
+    }
+
+    fn material(&mut self, spec: Spec) -> Result<SharedMaterial> {
+        let kind = spec.kind("material")?;
+        let rest = spec.rest();
+        Ok(match kind {
+            "lambertian" => Arc::new(Lambertian::new(self.texture(rest)?)),
+            "metal" => match rest.args.len() {
+                3 => Arc::new(Metal::new(rest.vector("metal")?, 0.0)),
+                _ => {
+                    let [r, g, b, fuzz] = rest.numbers("metal")?;
+                    Arc::new(Metal::new(Vector3::new(r, g, b), fuzz))
+                }
+            },
+            "conductor" => Arc::new(self.conductor(rest)?),
+            "dielectric" => self.dielectric(rest)?,
+            "diffuse_light" => Arc::new(DiffuseLight::new(self.texture(rest)?)),
+            "isotropic" => Arc::new(Isotropic::new(self.texture(rest)?)),
+            "normal_map" | "bump" => {
+                if !rest.args.is_empty() {
+                    return error(spec.line, format!("'{}' takes no arguments", kind));
+                }
+                let allowed: &[&str] = if kind == "normal_map" {
+                    &["map", "material"]
+                } else {
+                    &["height", "scale", "material"]
+                };
+                let block = Block::parse(spec.line, kind, rest.block, allowed, &[])?;
+                block.no_children()?;
+                let material = self.material(block.required("material")?)?;
+                if kind == "normal_map" {
+                    Arc::new(NormalMap::new(
+                        material,
+                        self.texture(block.required("map")?)?,
+                    ))
+                } else {
+                    let scale = match block.optional("scale") {
+                        Some(spec) => spec.number("scale")?,
+                        None => 1.0,
+                    };
+                    let height = self.texture(block.required("height")?)?;
+                    Arc::new(Bump::new(material, height, scale))
+                }
+            }
+            "mix" => {
+                if !rest.args.is_empty() {
+                    return error(spec.line, "'mix' takes no arguments");
+                }
+                let block = Block::parse(
+                    spec.line,
+                    kind,
+                    rest.block,
+                    &["first", "second", "mask"],
+                    &[],
+                )?;
+                block.no_children()?;
+                Arc::new(MixMaterial::new(
+                    self.material(block.required("first")?)?,
+                    self.material(block.required("second")?)?,
+                    self.texture(block.required("mask")?)?,
+                ))
+            }
+            "coated" => {
+                if !rest.args.is_empty() {
+                    return error(spec.line, "'coated' takes no arguments");
+                }
+                let block = Block::parse(
+                    spec.line,
+                    kind,
+                    rest.block,
+                    &["index", "roughness", "material"],
+                    &[],
+                )?;
+                block.no_children()?;
+                let index = match block.optional("index") {
+                    Some(spec) => spec.number("index")?,
+                    None => 1.5,
+                };
+                let roughness = match block.optional("roughness") {
+                    Some(spec) => spec.number("roughness")?,
+                    None => 0.0,
+                };
+                let base = self.material(block.required("material")?)?;
+                Arc::new(Coated::new(base, index, roughness))
+            }
+            name => {
+                let material = spec.word("a material name")?;
+                match self.materials.get(material) {
+                    Some(material) => material.clone(),
+                    None => return error(spec.line, format!("unknown material '{}'", name)),
+                }
+            }
+        })
+    }
+
+    /// `<metal> [roughness]` or `{ eta r g b; k r g b; [roughness] }`.
+    fn conductor(&self, spec: Spec) -> Result<Conductor> {
+        if spec.block.is_some() {
+            if !spec.args.is_empty() {
+                return error(spec.line, "'conductor' takes a metal name or a block");
+            }
+            let block = Block::parse(
+                spec.line,
+                "conductor",
+                spec.block,
+                &["eta", "k", "roughness"],
+                &[],
+            )?;
+            block.no_children()?;
+            let roughness = match block.optional("roughness") {
+                Some(spec) => spec.number("roughness")?,
+                None => 0.0,
+            };
+            return Ok(Conductor::new(
+                block.required("eta")?.vector("eta")?,
+                block.required("k")?.vector("k")?,
+                roughness,
+            ));
+        }
+        let name = spec.kind("metal name")?;
+        let roughness = match spec.rest() {
+            rest if rest.args.is_empty() => 0.0,
+            rest => rest.number("roughness")?,
+        };
+        match Conductor::named(name, roughness) {
+            Some(conductor) => Ok(conductor),
+            None => error(
+                spec.line,
+                format!(
+                    "unknown metal '{}', expected gold, silver, copper or aluminium",
+                    name
+                ),
+            ),
+        }
+    }
+
+    /// `<index> [roughness]` or `{ index <index>|cauchy a b|sellmeier b1 b2
+    /// b3 c1 c2 c3; [roughness]; [absorption r g b] }`.
+    fn dielectric(&self, spec: Spec) -> Result<SharedMaterial> {
+        let (ior, roughness, absorption) = if spec.block.is_some() {
+            if !spec.args.is_empty() {
+                return error(spec.line, "'dielectric' takes an index or a block");
+            }
+            let block = Block::parse(
+                spec.line,
+                "dielectric",
+                spec.block,
+                &["index", "roughness", "absorption"],
+                &[],
+            )?;
+            block.no_children()?;
+            let roughness = match block.optional("roughness") {
+                Some(spec) => Some(spec.number("roughness")?),
+                None => None,
+            };
+            let absorption = match block.optional("absorption") {
+                Some(spec) => spec.vector("absorption")?,
+                None => Vector3::zeros(),
+            };
+            let ior = self.ior(block.required("index")?)?;
+            (ior, roughness, absorption)
+        } else if spec.args.len() == 1 {
+            let index = spec.number("dielectric")?;
+            (IOR::Constant(index), None, Vector3::zeros())
+        } else {
+            let [index, roughness] = spec.numbers("dielectric")?;
+            (IOR::Constant(index), Some(roughness), Vector3::zeros())
+        };
+        Ok(match roughness {
+            Some(roughness) => {
+                Arc::new(RoughDielectric::with_ior(ior, roughness).absorption(absorption))
+            }
+            None => Arc::new(Dielectric::with_ior(ior).absorption(absorption)),
+        })
+    }
+
+    /// `<index>`, `cauchy a b` or `sellmeier b1 b2 b3 c1 c2 c3`, with the
+    /// wavelength in μm.
+    fn ior(&self, spec: Spec) -> Result<IOR> {
+        match spec.args.first() {
+            Some(Atom::Word(w)) if w == "cauchy" => {
+                let [a, b] = spec.rest().numbers("cauchy")?;
+                Ok(IOR::Cauchy { a, b })
+            }
+            Some(Atom::Word(w)) if w == "sellmeier" => {
+                let [b1, b2, b3, c1, c2, c3] = spec.rest().numbers("sellmeier")?;
+                Ok(IOR::Sellmeier {
+                    b: [b1, b2, b3],
+                    c: [c1, c2, c3],
+                })
+            }
+            Some(Atom::Word(w)) => error(
+                spec.line,
+                format!("unknown index '{}', expected cauchy or sellmeier", w),
+            ),
+            _ => Ok(IOR::Constant(spec.number("index")?)),
+        }
+    }
+
+    fn children(&mut self, block: &Block) -> Result<Vec<Box<dyn Hittable>>> {
+        block.children.iter().map(|e| self.hittable(e)).collect()
+    }
+
+    fn hittable(&mut self, entry: &Entry) -> Result<Box<dyn Hittable>> {
+        Ok(match entry.key.as_str() {
+            "sphere" => {
+                let block = Block::new(entry, &["center", "radius", "material"])?;
+                block.no_children()?;
+                Box::new(Sphere::new(
+                    block.required("center")?.vector("center")?,
+                    block.required("radius")?.number("radius")?,
+                    self.material(block.required("material")?)?,
+                ))
+            }
+            "moving_sphere" => {
+                let block =
+                    Block::new(entry, &["center0", "center1", "time", "radius", "material"])?;
+                block.no_children()?;
+                let [time0, time1] = match block.optional("time") {
+                    Some(spec) => spec.numbers("time")?,
+                    None => [self.time0, self.time1],
+                };
+                Box::new(MovingSphere::new(
+                    block.required("center0")?.vector("center0")?,
+                    block.required("center1")?.vector("center1")?,
+                    time0,
+                    time1,
+                    block.required("radius")?.number("radius")?,
+                    self.material(block.required("material")?)?,
+                ))
+            }
+            "rect" => {
+                let block = Block::new(entry, &["plane", "a", "b", "k", "material"])?;
+                block.no_children()?;
+                let plane = block.required("plane")?;
+                let plane = match plane.word("plane")? {
+                    "yz" => Plane::YZ,
+                    "zx" => Plane::ZX,
+                    "xy" => Plane::XY,
+                    other => {
+                        return error(
+                            plane.line,
+                            format!("unknown plane '{}', expected yz, zx or xy", other),
+                        )
+                    }
+                };
+                let [a0, a1] = block.required("a")?.numbers("a")?;
+                let [b0, b1] = block.required("b")?.numbers("b")?;
+                Box::new(AARect::new(
+                    plane,
+                    a0,
+                    a1,
+                    b0,
+                    b1,
+                    block.required("k")?.number("k")?,
+                    self.material(block.required("material")?)?,
+                ))
+            }
+            "quad" => {
+                let block = Block::new(entry, &["corner", "u", "v", "material"])?;
+                block.no_children()?;
+                let u = block.required("u")?.vector("u")?;
+                let v = block.required("v")?.vector("v")?;
+                if u.cross(&v).norm() == 0.0 {
+                    return error(entry.line, "the edges of 'quad' are parallel");
+                }
+                Box::new(Quad::new(
+                    block.required("corner")?.vector("corner")?,
+                    u,
+                    v,
+                    self.material(block.required("material")?)?,
+                ))
+            }
+            "cube" => {
+                let block = Block::new(entry, &["min", "max", "material"])?;
+                block.no_children()?;
+                Box::new(Cube::new(
+                    block.required("min")?.vector("min")?,
+                    block.required("max")?.vector("max")?,
+                    self.material(block.required("material")?)?,
+                ))
+            }
+            "disk" => {
+                let block = Block::new(entry, &["center", "radius", "material"])?;
+                block.no_children()?;
+                Box::new(Disk::new(
+                    block.required("center")?.vector("center")?,
+                    block.required("radius")?.number("radius")?,
+                    self.material(block.required("material")?)?,
+                ))
+            }
+            "cylinder" | "cone" | "paraboloid" => {
+                let block =
+                    Block::new(entry, &["center", "radius", "height", "capped", "material"])?;
+                block.no_children()?;
+                let center = block.required("center")?.vector("center")?;
+                let radius = block.required("radius")?.number("radius")?;
+                let height = block.required("height")?.number("height")?;
+                let capped = match block.optional("capped") {
+                    Some(spec) => {
+                        spec.flag("capped")?;
+                        true
+                    }
+                    None => false,
+                };
+                let material = self.material(block.required("material")?)?;
+                match entry.key.as_str() {
+                    "cylinder" => Box::new(Cylinder::new(center, radius, height, capped, material)),
+                    "cone" => Box::new(Cone::new(center, radius, height, capped, material)),
+                    _ => Box::new(Paraboloid::new(center, radius, height, capped, material)),
+                }
+            }
+            "mesh" => {
+                let block = Block::new(entry, &["file", "material"])?;
+                block.no_children()?;
+                let material = match block.optional("material") {
+                    Some(spec) => Some(self.material(spec)?),
+                    None => None,
+                };
+                let file = block.required("file")?;
+                let path = self.dir.join(file.string("file")?);
+                let mesh = match path.extension().and_then(|e| e.to_str()) {
+                    Some("ply") => ply::load(&path, material),
+                    _ => obj::load(&path, material),
+                };
+                match mesh {
+                    Ok(mesh) => Box::new(mesh),
+                    Err(e) => return error(file.line, format!("cannot load mesh: {}", e)),
+                }
+            }
+            "medium" => {
+                let block = Block::new(entry, &["density", "albedo"])?;
+                let boundary = self.hittable(block.only_child()?)?;
+                Box::new(ConstantMedium::new(
+                    boundary,
+                    block.required("density")?.number("density")?,
+                    self.texture(block.required("albedo")?)?,
+                ))
+            }
+            "filled" => {
+                let block = Block::new(entry, &["absorption", "density", "albedo"])?;
+                let boundary = self.hittable(block.only_child()?)?;
+                let absorption = match block.optional("absorption") {
+                    Some(spec) => spec.vector("absorption")?,
+                    None => Vector3::zeros(),
+                };
+                let mut medium = Medium::new(absorption);
+                if let Some(density) = block.optional("density") {
+                    medium = medium.scattering(
+                        density.number("density")?,
+                        block.required("albedo")?.vector("albedo")?,
+                    );
+                }
+                Box::new(Filled::new(boundary, medium))
+            }
+            "translate" | "rotate" | "scale" | "look_at" | "transform" => {
+                // Directly nested placements are folded into one matrix.
+                let mut matrix = Matrix4::identity();
+                let mut entry = entry;
+                while let Some((m, child)) = self.placement(entry)? {
+                    matrix *= m;
+                    entry = child;
+                }
+                if !matrix.is_invertible() {
+                    return error(entry.line, "the transform of this object is not invertible");
+                }
+                Box::new(Transform::new(self.hittable(entry)?, matrix))
+            }
+            "animate" => {
+                let block = Block::with_repeated(entry, &[], &["key"])?;
+                let keys = block.all("key");
+                if keys.is_empty() {
+                    return error(entry.line, "'animate' needs at least one 'key'");
+                }
+                let keyframes = keys
+                    .into_iter()
+                    .map(|key| self.keyframe(key))
+                    .collect::<Result<Vec<_>>>()?;
+                let child = self.hittable(block.only_child()?)?;
+                Box::new(AnimatedTransform::new(child, keyframes))
+            }
+            "flip" => {
+                let block = Block::new(entry, &[])?;
+                Box::new(FlipNormals::new(self.hittable(block.only_child()?)?))
+            }
+            "union" | "intersection" | "difference" => {
+                let block = Block::new(entry, &[])?;
+                let (a, b) = block.two_children()?;
+                let operation = match entry.key.as_str() {
+                    "union" => Operation::Union,
+                    "intersection" => Operation::Intersection,
+                    _ => Operation::Difference,
+                };
+                Box::new(CSG::new(operation, self.hittable(a)?, self.hittable(b)?))
+            }
+            "list" => {
+                let block = Block::new(entry, &[])?;
+                let mut list = HittableList::default();
+                for child in self.children(&block)? {
+                    list.push(child);
+                }
+                Box::new(list)
+            }
+            "bvh" => {
+                let block = Block::new(entry, &[])?;
+                let children = self.children(&block)?;
+                if children.is_empty() {
+                    return error(entry.line, "'bvh' must contain at least one object");
+                }
+                Box::new(BVH::new(children, self.bounds.0, self.bounds.1))
+            }
+            other => return error(entry.line, format!("unknown object '{}'", other)),
+        })
+    }
+
+    fn keyframe(&self, entry: &Entry) -> Result<Keyframe> {
+        let block = Block::new(entry, &["time", "translate", "rotate", "scale"])?;
+        block.no_children()?;
+        let mut keyframe = Keyframe::new(block.required("time")?.number("time")?);
+        if let Some(spec) = block.optional("translate") {
+            keyframe.translation = spec.vector("translate")?;
+        }
+        if let Some(spec) = block.optional("rotate") {
+            let [x, y, z, angle] = spec.numbers("rotate")?;
+            if [x, y, z] == [0.0; 3] {
+                return error(spec.line, "the rotation axis must not be zero");
+            }
+            keyframe.axis = Unit::new_normalize(Vector3::new(x, y, z));
+            keyframe.angle = angle.to_radians();
+        }
+        if let Some(spec) = block.optional("scale") {
+            keyframe.scale = match spec.args.len() {
+                1 => Vector3::repeat(spec.number("scale")?),
+                _ => spec.vector("scale")?,
+            };
+            if keyframe.scale.iter().any(|&s| s == 0.0) {
+                return error(spec.line, "the scale must not be zero");
+            }
+        }
+        Ok(keyframe)
+    }
+
+    /// The matrix and the child of a placement object, or `None` if `entry`
+    /// is some other object.
+    fn placement<'e>(&self, entry: &'e Entry) -> Result<Option<(Matrix4<f32>, &'e Entry)>> {
+        let (block, matrix) = match entry.key.as_str() {
+            "translate" => {
+                let block = Block::new(entry, &["offset"])?;
+                let offset = block.required("offset")?.vector("offset")?;
+                (block, Matrix4::new_translation(&offset))
+            }
+            "rotate" => {
+                let block = Block::new(entry, &["axis", "angle", "quaternion"])?;
+                let rotation = match block.optional("quaternion") {
+                    Some(spec) => {
+                        let [w, x, y, z] = spec.numbers("quaternion")?;
+                        UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z))
+                    }
+                    None => {
+                        let spec = block.required("axis")?;
+                        let axis = match spec.args {
+                            [Atom::Word(_)] => match spec.word("axis")? {
+                                "x" => Vector3::x(),
+                                "y" => Vector3::y(),
+                                "z" => Vector3::z(),
+                                other => {
+                                    return error(
+                                        spec.line,
+                                        format!("unknown axis '{}', expected x, y or z", other),
+                                    )
+                                }
+                            },
+                            _ => spec.vector("axis")?,
+                        };
+                        if axis == Vector3::zeros() {
+                            return error(spec.line, "the axis must not be zero");
+                        }
+                        let angle = block.required("angle")?.number("angle")?;
+                        UnitQuaternion::from_axis_angle(
+                            &Unit::new_normalize(axis),
+                            angle.to_radians(),
+                        )
+                    }
+                };
+                (block, rotation.to_homogeneous())
+            }
+            "scale" => {
+                let block = Block::new(entry, &["factor"])?;
+                let spec = block.required("factor")?;
+                let factors = match spec.args.len() {
+                    1 => Vector3::repeat(spec.number("factor")?),
+                    _ => spec.vector("factor")?,
+                };
+                (block, Matrix4::new_nonuniform_scaling(&factors))
+            }
+            "look_at" => {
+                let block = Block::new(entry, &["from", "at", "up"])?;
+                let from = block.required("from")?.vector("from")?;
+                let at = block.required("at")?.vector("at")?;
+                let up = match block.optional("up") {
+                    Some(spec) => spec.vector("up")?,
+                    None => Vector3::y(),
+                };
+                let isometry = Isometry3::face_towards(&Point3::from(from), &Point3::from(at), &up);
+                (block, isometry.to_homogeneous())
+            }
+            "transform" => {
+                let block = Block::new(entry, &["matrix"])?;
+                let m: [f32; 16] = block.required("matrix")?.numbers("matrix")?;
+                (block, Matrix4::from_row_slice(&m))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((matrix, block.only_child()?)))
+    }
+
+    fn definition<'e>(&self, entry: &'e Entry) -> Result<(String, Spec<'e>)> {
+        match entry.args.first() {
+            Some(Atom::Word(name)) if HITTABLES.contains(&name.as_str()) => {
+                error(entry.line, format!("'{}' cannot be used as a name", name))
+            }
+            Some(Atom::Word(name)) => Ok((name.clone(), entry.spec().rest())),
+            _ => error(entry.line, format!("'{}' expects a name", entry.key)),
+        }
+    }
+}
+
+fn load_source(
+    source: &str,
+    dir: &Path,
+    aspect: f32,
+    seed: u64,
+    bounds: Option<(f32, f32)>,
+) -> Result<(Box<dyn Hittable>, CameraPath)> {
+    let tokens = tokenize(source)?;
+    let entries = parse_entries(&tokens, &mut 0, None)?;
+    let mut loader = Loader {
+        dir,
+        rng: Sampler::from_seed(seed),
+        time0: 0.0,
+        time1: 1.0,
+        bounds: (0.0, 1.0),
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+    };
+    let mut cameras = entries.iter().filter(|e| e.key == "camera");
+    let camera = match (cameras.next(), cameras.next()) {
+        (Some(camera), None) => camera,
+        (None, _) => return error(1, "the scene has no camera"),
+        (Some(_), Some(second)) => return error(second.line, "only one camera is allowed"),
+    };
+    if let Some(time) = camera.block.iter().flatten().find(|e| e.key == "time") {
+        [loader.time0, loader.time1] = time.spec().numbers("time")?;
+    }
+    loader.bounds = bounds.unwrap_or((loader.time0, loader.time1));
+    let cam = loader.camera(camera, aspect)?;
+    let mut world = HittableList::default();
+    for entry in &entries {
+        match entry.key.as_str() {
+            "camera" => {}
+            "texture" => {
+                let (name, spec) = loader.definition(entry)?;
+                let texture = loader.texture(spec)?;
+                if loader.textures.insert(name.clone(), texture).is_some() {
+                    return error(entry.line, format!("texture '{}' is already defined", name));
+                }
+            }
+            "material" => {
+                let (name, spec) = loader.definition(entry)?;
+                let material = loader.material(spec)?;
+                if loader.materials.insert(name.clone(), material).is_some() {
+                    return error(
+                        entry.line,
+                        format!("material '{}' is already defined", name),
+                    );
+                }
+            }
+            _ => world.push(loader.hittable(entry)?),
+        }
+    }
+    Ok((Box::new(world), cam))
+}
+
+/// Loads a scene description file, whose format `scenes/README.md` describes.
+/// Relative paths are resolved against the directory of the file, `seed`
+/// drives the noise textures, and the bounding boxes of moving objects cover
+/// `bounds`, or the camera's shutter interval if it is `None`.
+pub fn load(
+    path: &Path,
+    aspect: f32,
+    seed: u64,
+    bounds: Option<(f32, f32)>,
+) -> io::Result<(Box<dyn Hittable>, CameraPath)> {
+    let source = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    load_source(&source, dir, aspect, seed, bounds).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), e.line, e.message),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    const CAMERA: &str = "camera { look_from 0 0 -10; look_at 0 0 0; fov 40 }\n";
+
+    fn load_str(source: &str) -> Result<(Box<dyn Hittable>, CameraPath)> {
+        load_source(source, Path::new(""), 1.0, 0, None)
+    }
+
+    /// The line and message of the error `source` fails to load with.
+    fn load_error(source: &str) -> (usize, String) {
+        match load_str(source) {
+            Ok(_) => panic!("loaded an invalid scene:\n{}", source),
+            Err(e) => (e.line, e.message),
+        }
+    }
+
+    #[test]
+    fn loads_a_minimal_scene() {
+        let source = format!(
+            "{}material red lambertian 1 0 0\nsphere {{ center 0 0 0; radius 2; material red }}",
+            CAMERA
+        );
+        let (world, cam) = load_str(&source).unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(cam.key(0.0).look_from, Vector3::new(0.0, 0.0, -10.0));
+        let bbox = world.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(bbox.min, Vector3::repeat(-2.0));
+        assert_eq!(bbox.max, Vector3::repeat(2.0));
+        let ray = Ray::new(Vector3::new(0.0, 0.0, -10.0), Vector3::z(), 0.0);
+        let hit = world.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let (line, message) = load_error(&format!("{}teapot {{ }}", CAMERA));
+        assert_eq!(line, 2);
+        assert_eq!(message, "unknown object 'teapot'");
+        let (line, message) = load_error(&format!(
+            "{}sphere {{ center 0 0 0; radius 1; colour 1 0 0 }}",
+            CAMERA
+        ));
+        assert_eq!(line, 2);
+        assert_eq!(message, "unknown property 'colour' in 'sphere'");
+        let (_, message) = load_error(&format!(
+            "{}sphere {{ center 0 0 0; radius 1; material chrome }}",
+            CAMERA
+        ));
+        assert_eq!(message, "unknown material 'chrome'");
+    }
+
+    #[test]
+    fn rejects_wrong_argument_counts() {
+        let (_, message) = load_error(&format!(
+            "{}sphere {{ center 0 0; radius 1; material 1 1 1 }}",
+            CAMERA
+        ));
+        assert_eq!(message, "center expects 3 numbers");
+        let (_, message) = load_error(&format!(
+            "{}sphere {{ center 0 0 0; radius 1 2; material 1 1 1 }}",
+            CAMERA
+        ));
+        assert_eq!(message, "radius expects a number");
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let source = "# A comment.\n\ncamera {\n    look_from 0 0 -10\n    look_at 0 0 0\n    fov 40\n}\n\nsphere {\n    center 0 0 0\n    radius one\n}\n";
+        assert_eq!(
+            load_error(source),
+            (11, "radius expects a number".to_string())
+        );
+        let (line, message) = load_error(&format!("{}sphere {{ radius 1", CAMERA));
+        assert_eq!((line, message.as_str()), (2, "'{' is never closed"));
+        assert_eq!(
+            load_error("sphere { }"),
+            (1, "the scene has no camera".to_string())
+        );
+        let (line, message) = load_error(&format!("{}\n{}", CAMERA, CAMERA));
+        assert_eq!((line, message.as_str()), (3, "only one camera is allowed"));
+    }
+}
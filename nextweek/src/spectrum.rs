@@ -0,0 +1,181 @@
+use crate::sampler::Sampler;
+use nalgebra::{Matrix3, Vector3, Vector4};
+use rand::Rng;
+
+/// Range of wavelengths, in nm, that spectral rendering samples.
+pub const LAMBDA_MIN: f32 = 380.0;
+pub const LAMBDA_MAX: f32 = 720.0;
+
+/// Wavelength of the sodium d line, in nm, at which indices of refraction
+/// are taken when rendering in RGB.
+pub const D_LINE: f32 = 587.6;
+
+/// Borders, in nm, between the blue, green and red bands that RGB colors
+/// are upsampled to.
+const BAND_EDGES: [f32; 2] = [490.0, 590.0];
+
+/// Gaussian with different widths below and above its mean.
+fn gaussian(x: f32, mean: f32, below: f32, above: f32) -> f32 {
+    let sigma = if x < mean { below } else { above };
+    (-0.5 * ((x - mean) / sigma).powi(2)).exp()
+}
+
+/// CIE 1931 color matching functions at `lambda`, in nm, from the
+/// multi-lobe fit of Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f32) -> Vector3<f32> {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+    Vector3::new(x, y, z)
+}
+
+/// Index of the band of `lambda` among the upsampling bands.
+fn band(lambda: f32) -> usize {
+    BAND_EDGES
+        .iter()
+        .take_while(|&&edge| lambda >= edge)
+        .count()
+}
+
+/// Conversion between linear sRGB and spectra sampled at four wavelengths.
+/// Both directions are white balanced, so that a flat spectrum of 1 is
+/// white (1, 1, 1) and back.
+#[derive(Clone)]
+pub struct Spectrum {
+    // XYZ integrals to RGB
+    to_rgb: Matrix3<f32>,
+    // RGB to the heights of the blue, green and red bands
+    from_rgb: Matrix3<f32>,
+}
+
+impl Spectrum {
+    pub fn new() -> Self {
+        let xyz_to_rgb = Matrix3::new(
+            3.2406, -1.5372, -0.4986, //
+            -0.9689, 1.8758, 0.0415, //
+            0.0557, -0.2040, 1.0570,
+        );
+        // Integrate the matching functions over the bands, with a midpoint
+        // rule in steps of a quarter nm.
+        let mut bands = Matrix3::zeros();
+        let steps = ((LAMBDA_MAX - LAMBDA_MIN) * 4.0) as usize;
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f32 + 0.5) / 4.0;
+            let mut column = bands.column_mut(band(lambda));
+            column += xyz_to_rgb * cie_xyz(lambda) / 4.0;
+        }
+        let white = bands.column_sum();
+        let balance = Matrix3::from_diagonal(&white.map(|w| 1.0 / w));
+        let band_to_rgb = balance * bands;
+        Spectrum {
+            to_rgb: balance * xyz_to_rgb,
+            from_rgb: band_to_rgb
+                .try_inverse()
+                .expect("band colors are independent"),
+        }
+    }
+
+    /// A hero wavelength, uniform over the visible range, and three more
+    /// spaced evenly after it, wrapping around.
+    pub fn sample_wavelengths(rng: &mut Sampler) -> Vector4<f32> {
+        let u = rng.gen::<f32>();
+        Vector4::from_fn(|i, _| {
+            let offset = (u + i as f32 / 4.0).fract();
+            LAMBDA_MIN + offset * (LAMBDA_MAX - LAMBDA_MIN)
+        })
+    }
+
+    /// The spectrum of the reflectance `rgb` at the wavelengths `lambda`, a
+    /// step function over the three bands. Heights are clamped to [0, 1] so
+    /// that saturated colors never reflect more than they receive.
+    pub fn upsample(&self, rgb: &Vector3<f32>, lambda: &Vector4<f32>) -> Vector4<f32> {
+        self.upsample_emission(rgb, lambda).map(|h| h.min(1.0))
+    }
+
+    /// The spectrum of the radiance `rgb` at the wavelengths `lambda`, a step
+    /// function over the three bands whose heights are only kept
+    /// non-negative.
+    pub fn upsample_emission(&self, rgb: &Vector3<f32>, lambda: &Vector4<f32>) -> Vector4<f32> {
+        let heights = (self.from_rgb * rgb).map(|h| h.max(0.0));
+        lambda.map(|l| heights[band(l)])
+    }
+
+    /// Color of the radiance `radiance` sampled at the wavelengths `lambda`,
+    /// which are uniformly distributed.
+    pub fn to_rgb(&self, radiance: &Vector4<f32>, lambda: &Vector4<f32>) -> Vector3<f32> {
+        let xyz = radiance
+            .iter()
+            .zip(lambda.iter())
+            .map(|(&l, &lambda)| l * cie_xyz(lambda))
+            .sum::<Vector3<f32>>();
+        self.to_rgb * xyz * (LAMBDA_MAX - LAMBDA_MIN) / 4.0
+    }
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Spectrum::new()
+    }
+}
+
+/// Index of refraction as a function of wavelength.
+#[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum IOR {
+    Constant(f32),
+    /// n = a + b / λ², with λ in μm.
+    Cauchy {
+        a: f32,
+        b: f32,
+    },
+    /// n² = 1 + Σ b λ² / (λ² - c), with λ in μm.
+    Sellmeier {
+        b: [f32; 3],
+        c: [f32; 3],
+    },
+}
+
+impl IOR {
+    /// The index at `lambda` in nm, or at the d line without a wavelength.
+    pub fn at(&self, lambda: Option<f32>) -> f32 {
+        let l2 = (lambda.unwrap_or(D_LINE) / 1000.0).powi(2);
+        match self {
+            IOR::Constant(n) => *n,
+            IOR::Cauchy { a, b } => a + b / l2,
+            IOR::Sellmeier { b, c } => {
+                let sum: f32 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    /// Whether the index changes with wavelength.
+    pub fn dispersive(&self) -> bool {
+        !matches!(self, IOR::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One wavelength in each band, and one more in the green.
+    const LAMBDA: Vector4<f32> = Vector4::new(450.0, 520.0, 560.0, 650.0);
+
+    #[test]
+    fn clamps_reflectances_but_not_emission() {
+        let spectrum = Spectrum::new();
+        let red = spectrum.upsample(&Vector3::new(1.0, 0.0, 0.0), &LAMBDA);
+        // The red band of pure red is slightly above 1 before clamping.
+        assert!(red.iter().all(|&h| (0.0..=1.0).contains(&h)), "{}", red);
+        assert_eq!(red.w, 1.0);
+        let white = spectrum.upsample(&Vector3::repeat(1.0), &LAMBDA);
+        assert!(white.iter().all(|&h| (h - 1.0).abs() < 1e-3), "{}", white);
+        let light = spectrum.upsample_emission(&Vector3::repeat(15.0), &LAMBDA);
+        assert!(light.iter().all(|&h| (h - 15.0).abs() < 1e-2), "{}", light);
+    }
+}
@@ -14,6 +14,18 @@ fn get_sphere_uv(p: &Vector3<f32>) -> (f32, f32) {
     (u, v)
 }
 
+/// Derivatives of the point at `p` on a unit sphere scaled by `radius` by
+/// the uvs of `get_sphere_uv`. They vanish at the poles.
+fn get_sphere_tangents(p: &Vector3<f32>, radius: f32) -> (Vector3<f32>, Vector3<f32>) {
+    let dpdu = 2.0 * f32::consts::PI * radius * Vector3::new(p.z, 0.0, -p.x);
+    let rho = (p.x * p.x + p.z * p.z).sqrt();
+    if rho == 0.0 {
+        return (dpdu, Vector3::zeros());
+    }
+    let dpdv = f32::consts::PI * radius * Vector3::new(-p.y * p.x / rho, rho, -p.y * p.z / rho);
+    (dpdu, dpdv)
+}
+
 #[derive(Clone)]
 pub struct Sphere<M: Material> {
     center: Vector3<f32>,
@@ -32,7 +44,7 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let oc = ray.origin() - self.center;
         let a = ray.direction().dot(&ray.direction());
         let b = oc.dot(&ray.direction());
@@ -45,13 +57,18 @@ impl<M: Material> Hittable for Sphere<M> {
                 let p = ray.point_at_parameter(t);
                 let normal = (p - self.center) / self.radius;
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    medium: None,
                 });
             }
             let t = (-b + sqrt_discriminant) / a;
@@ -59,13 +76,18 @@ impl<M: Material> Hittable for Sphere<M> {
                 let p = ray.point_at_parameter(t);
                 let normal = (p - self.center) / self.radius;
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    medium: None,
                 });
             }
         }
@@ -115,7 +137,7 @@ impl<M: Material> MovingSphere<M> {
 }
 
 impl<M: Material> Hittable for MovingSphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let center = self.center(ray.time());
         let oc = ray.origin() - center;
         let a = ray.direction().dot(&ray.direction());
@@ -129,13 +151,18 @@ impl<M: Material> Hittable for MovingSphere<M> {
                 let p = ray.point_at_parameter(t);
                 let normal = (p - center) / self.radius;
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    medium: None,
                 });
             }
             let t = (-b + sqrt_discriminant) / a;
@@ -143,13 +170,18 @@ impl<M: Material> Hittable for MovingSphere<M> {
                 let p = ray.point_at_parameter(t);
                 let normal = (p - center) / self.radius;
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    medium: None,
                 });
             }
         }
@@ -161,7 +193,7 @@ impl<M: Material> Hittable for MovingSphere<M> {
         let min0 = self.center(t0) - radius;
         let max0 = self.center(t0) + radius;
         let min1 = self.center(t1) - radius;
-        let max1 = self.center(t0) + radius;
+        let max1 = self.center(t1) + radius;
         let aabb0 = AABB::new(min0, max0);
         let aabb1 = AABB::new(min1, max1);
         Some(aabb::surrounding_box(&aabb0, &aabb1))
@@ -1,10 +1,18 @@
 use crate::perlin::Perlin;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
+use std::sync::Arc;
 
 pub trait Texture: Sync {
     fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32>;
 }
 
+impl<T: Texture + Send + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        (**self).value(u, v, p)
+    }
+}
+
 #[derive(Clone)]
 pub struct ConstantTexture {
     color: Vector3<f32>,
@@ -31,7 +39,6 @@ pub struct CheckerTexture<T: Texture, U: Texture> {
 }
 
 impl<T: Texture, U: Texture> CheckerTexture<T, U> {
-    #[allow(dead_code)]
     pub fn new(odd: T, even: U) -> Self {
         CheckerTexture { odd, even }
     }
@@ -55,9 +62,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f32) -> Self {
+    pub fn new(scale: f32, rng: &mut Sampler) -> Self {
         NoiseTexture {
-            noise: Perlin::new(),
+            noise: Perlin::new(rng),
             scale,
         }
     }
@@ -103,3 +110,26 @@ impl Texture for ImageTexture {
         Vector3::new(r, g, b)
     }
 }
+
+/// Colors given at the vertices of a mesh, shared by its triangles. Each
+/// triangle blends the colors of its own corners with the barycentric
+/// coordinates it reports as uvs when it has no texture coordinates.
+#[derive(Clone)]
+pub struct VertexColorTexture {
+    colors: Arc<[Vector3<f32>]>,
+    corners: [usize; 3],
+}
+
+impl VertexColorTexture {
+    /// The texture of the triangle with `corners` among the vertex `colors`.
+    pub fn new(colors: Arc<[Vector3<f32>]>, corners: [usize; 3]) -> Self {
+        VertexColorTexture { colors, corners }
+    }
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
+        let [c0, c1, c2] = self.corners.map(|i| self.colors[i]);
+        (1.0 - u - v) * c0 + u * c1 + v * c2
+    }
+}
@@ -0,0 +1,103 @@
+use clap::ValueEnum;
+use nalgebra::{Matrix3, Vector3};
+
+fn luminance(c: &Vector3<f32>) -> f32 {
+    c.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+fn srgb_oetf(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Scales `c` so that its luminance becomes `f(luminance)`, keeping the hue.
+fn map_luminance(c: Vector3<f32>, f: impl Fn(f32) -> f32) -> Vector3<f32> {
+    let l = luminance(&c);
+    if l > 0.0 {
+        c * (f(l) / l)
+    } else {
+        Vector3::zeros()
+    }
+}
+
+// Stephen Hill's fit of the ACES RRT and sRGB ODT.
+fn aces_fitted(c: Vector3<f32>) -> Vector3<f32> {
+    let input = Matrix3::new(
+        0.59719, 0.35458, 0.04823, 0.07600, 0.90834, 0.01566, 0.02840, 0.13383, 0.83777,
+    );
+    let output = Matrix3::new(
+        1.60475, -0.53108, -0.07367, -0.10208, 1.10813, -0.00605, -0.00327, -0.07276, 1.07602,
+    );
+    let v = input * c;
+    let a = v.map(|x| x * (x + 0.024_578_6) - 0.000_090_537);
+    let b = v.map(|x| x * (0.983_729 * x + 0.432_951) + 0.238_081);
+    output * a.component_div(&b)
+}
+
+// John Hable's Uncharted 2 curve.
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+fn filmic(c: Vector3<f32>) -> Vector3<f32> {
+    const EXPOSURE_BIAS: f32 = 2.0;
+    const WHITE: f32 = 11.2;
+    c.map(|x| hable(EXPOSURE_BIAS * x) / hable(WHITE))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Operator {
+    /// Clip linear radiance to [0, 1]
+    Clamp,
+    /// Reinhard on luminance, L / (1 + L)
+    Reinhard,
+    /// Reinhard with a white point that maps to 1
+    ReinhardExtended,
+    /// Hill's fitted ACES filmic curve
+    Aces,
+    /// Hable's Uncharted 2 filmic curve
+    Filmic,
+}
+
+/// Turns linear scene radiance into display values: exposure in stops,
+/// a tone curve and the sRGB transfer function.
+pub struct ToneMapper {
+    operator: Operator,
+    exposure: f32,
+    white: f32,
+}
+
+impl ToneMapper {
+    pub fn new(operator: Operator, exposure: f32, white: f32) -> Self {
+        ToneMapper {
+            operator,
+            exposure,
+            white,
+        }
+    }
+
+    /// Display-referred linear color in [0, 1].
+    pub fn map(&self, c: Vector3<f32>) -> Vector3<f32> {
+        let c = c.map(|x| x.max(0.0)) * self.exposure.exp2();
+        let mapped = match self.operator {
+            Operator::Clamp => c,
+            Operator::Reinhard => map_luminance(c, |l| l / (1.0 + l)),
+            Operator::ReinhardExtended => {
+                let white2 = self.white.powi(2);
+                map_luminance(c, |l| l * (1.0 + l / white2) / (1.0 + l))
+            }
+            Operator::Aces => aces_fitted(c),
+            Operator::Filmic => filmic(c),
+        };
+        mapped.map(|x| x.clamp(0.0, 1.0))
+    }
+
+    pub fn to_srgb8(&self, c: Vector3<f32>) -> [u8; 3] {
+        let c = self.map(c).map(|x| (255.0 * srgb_oetf(x)).round() as u8);
+        [c.x, c.y, c.z]
+    }
+}
@@ -0,0 +1,320 @@
+use crate::aabb;
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use nalgebra::{Isometry3, Matrix4, Point3, Unit, UnitQuaternion, Vector3};
+use std::f32;
+
+/// Places an object with an affine matrix. Rays are taken into object space
+/// with the inverse, and normals back to world space with its transpose.
+pub struct Transform<H: Hittable> {
+    matrix: Matrix4<f32>,
+    inverse: Matrix4<f32>,
+    hittable: H,
+}
+
+#[allow(dead_code)]
+impl<H: Hittable> Transform<H> {
+    /// Panics if `matrix` cannot be inverted.
+    pub fn new(hittable: H, matrix: Matrix4<f32>) -> Self {
+        let inverse = matrix
+            .try_inverse()
+            .expect("transform matrix is not invertible");
+        Transform {
+            matrix,
+            inverse,
+            hittable,
+        }
+    }
+
+    pub fn translate(hittable: H, offset: Vector3<f32>) -> Self {
+        Transform::new(hittable, Matrix4::new_translation(&offset))
+    }
+
+    pub fn scale(hittable: H, factors: Vector3<f32>) -> Self {
+        Transform::new(hittable, Matrix4::new_nonuniform_scaling(&factors))
+    }
+
+    /// Rotates by `angle` degrees about `axis`, counterclockwise when the axis
+    /// points at the viewer.
+    pub fn rotate(hittable: H, axis: Vector3<f32>, angle: f32) -> Self {
+        let rotation =
+            UnitQuaternion::from_axis_angle(&Unit::new_normalize(axis), angle.to_radians());
+        Transform::quaternion(hittable, rotation)
+    }
+
+    pub fn quaternion(hittable: H, rotation: UnitQuaternion<f32>) -> Self {
+        Transform::new(hittable, rotation.to_homogeneous())
+    }
+
+    /// Moves the object's origin to `from` and turns its z axis towards `at`,
+    /// keeping its y axis as close to `up` as possible.
+    pub fn look_at(hittable: H, from: Vector3<f32>, at: Vector3<f32>, up: Vector3<f32>) -> Self {
+        let isometry = Isometry3::face_towards(&Point3::from(from), &Point3::from(at), &up);
+        Transform::new(hittable, isometry.to_homogeneous())
+    }
+
+    /// Applies `matrix` after the current transform. Composing matrices this
+    /// way costs one ray transform per hit test, where nesting `Transform`s
+    /// costs one per level and loosens the bounding box at each of them.
+    pub fn then(self, matrix: Matrix4<f32>) -> Self {
+        Transform::new(self.hittable, matrix * self.matrix)
+    }
+}
+
+/// Hits `hittable` as placed by `matrix`, whose inverse is `inverse`.
+fn hit_placed<'a, H: Hittable>(
+    hittable: &'a H,
+    matrix: &Matrix4<f32>,
+    inverse: &Matrix4<f32>,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    // The direction is not renormalized, so distances along the ray keep
+    // their meaning in object space.
+    let origin = inverse.transform_point(&Point3::from(ray.origin())).coords;
+    let direction = inverse.transform_vector(&ray.direction());
+    let local_ray = Ray::new(origin, direction, ray.time());
+    hittable.hit(&local_ray, t_min, t_max).map(|mut hit| {
+        hit.p = matrix.transform_point(&Point3::from(hit.p)).coords;
+        hit.dpdu = matrix.transform_vector(&hit.dpdu);
+        hit.dpdv = matrix.transform_vector(&hit.dpdv);
+        let to_world =
+            |normal: &Vector3<f32>| inverse.fixed_slice::<3, 3>(0, 0).tr_mul(normal).normalize();
+        hit.normal = to_world(&hit.normal);
+        hit.shading_normal = to_world(&hit.shading_normal);
+        hit
+    })
+}
+
+impl<H: Hittable> Hittable for Transform<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_placed(
+            &self.hittable,
+            &self.matrix,
+            &self.inverse,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    /// Bounds the transformed box of the object (Arvo 1990), which is exact
+    /// for the box itself.
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1).map(|bbox| {
+            let mut min: Vector3<f32> = self.matrix.fixed_slice::<3, 1>(0, 3).into();
+            let mut max = min;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let a = self.matrix[(i, j)] * bbox.min[j];
+                    let b = self.matrix[(i, j)] * bbox.max[j];
+                    min[i] += a.min(b);
+                    max[i] += a.max(b);
+                }
+            }
+            AABB::new(min, max)
+        })
+    }
+}
+
+/// Pose of an animated object at one point in time. Scale is applied first,
+/// then rotation, then translation. The rotation is kept as an axis and an
+/// angle in radians, which may exceed a full turn.
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Vector3<f32>,
+    pub axis: Unit<Vector3<f32>>,
+    pub angle: f32,
+    pub scale: Vector3<f32>,
+}
+
+impl Keyframe {
+    /// The identity pose at `time`.
+    pub fn new(time: f32) -> Self {
+        Keyframe {
+            time,
+            translation: Vector3::zeros(),
+            axis: Vector3::z_axis(),
+            angle: 0.0,
+            scale: Vector3::repeat(1.0),
+        }
+    }
+
+    fn rotation(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_axis_angle(&self.axis, self.angle)
+    }
+}
+
+/// The axis and angles of two keys that turn about the same axis, counting
+/// a key without rotation as turning about the other's.
+fn shared_axis(a: &Keyframe, b: &Keyframe) -> Option<(Unit<Vector3<f32>>, f32, f32)> {
+    let cos = a.axis.dot(&b.axis);
+    if b.angle == 0.0 || cos > 1.0 - 1e-6 {
+        Some((a.axis, a.angle, b.angle))
+    } else if a.angle == 0.0 {
+        Some((b.axis, 0.0, b.angle))
+    } else if cos < -1.0 + 1e-6 {
+        Some((a.axis, a.angle, -b.angle))
+    } else {
+        None
+    }
+}
+
+/// Angle turned from key `a` to key `b`: the difference of their angles
+/// about a shared axis, however large, and otherwise the shortest arc.
+fn turn(a: &Keyframe, b: &Keyframe) -> f32 {
+    match shared_axis(a, b) {
+        Some((_, from, to)) => (to - from).abs(),
+        None => a.rotation().angle_to(&b.rotation()),
+    }
+}
+
+// Longest rotation between two samples of the motion bounds.
+const MAX_BOUNDS_STEP: f32 = f32::consts::PI / 32.0;
+
+/// Moves an object through keyframes by the time of each ray, interpolating
+/// translation and scale linearly. Keys rotating about the same axis have
+/// their angles interpolated, so they may be a turn or more apart; other
+/// keys are joined spherically along the shortest arc. The object holds its
+/// first and last pose outside the keyframe times.
+pub struct AnimatedTransform<H: Hittable> {
+    keyframes: Vec<Keyframe>,
+    hittable: H,
+}
+
+impl<H: Hittable> AnimatedTransform<H> {
+    /// Panics if there are no keyframes or a scale factor is zero.
+    pub fn new(hittable: H, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "animation without keyframes");
+        assert!(
+            keyframes.iter().all(|k| k.scale.iter().all(|&s| s != 0.0)),
+            "keyframe scale is zero"
+        );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        AnimatedTransform {
+            keyframes,
+            hittable,
+        }
+    }
+
+    /// The keyframes before and after `time`, the same one outside them.
+    fn keys(&self, time: f32) -> (&Keyframe, &Keyframe) {
+        let k = &self.keyframes;
+        match k.partition_point(|k| k.time <= time) {
+            0 => (&k[0], &k[0]),
+            i if i == k.len() => (&k[i - 1], &k[i - 1]),
+            i => (&k[i - 1], &k[i]),
+        }
+    }
+
+    /// Interpolated translation, rotation and scale at `time`.
+    fn pose(&self, time: f32) -> (Vector3<f32>, UnitQuaternion<f32>, Vector3<f32>) {
+        let (a, b) = self.keys(time);
+        if a.time == b.time {
+            return (a.translation, a.rotation(), a.scale);
+        }
+        let s = (time - a.time) / (b.time - a.time);
+        let rotation = match shared_axis(a, b) {
+            Some((axis, from, to)) => {
+                UnitQuaternion::from_axis_angle(&axis, from + (to - from) * s)
+            }
+            None => a.rotation().slerp(&b.rotation(), s),
+        };
+        (
+            a.translation.lerp(&b.translation, s),
+            rotation,
+            a.scale.lerp(&b.scale, s),
+        )
+    }
+
+    fn matrices(&self, time: f32) -> (Matrix4<f32>, Matrix4<f32>) {
+        let (translation, rotation, scale) = self.pose(time);
+        let matrix = Matrix4::new_translation(&translation)
+            * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&scale);
+        let inverse = Matrix4::new_nonuniform_scaling(&scale.map(|s| 1.0 / s))
+            * rotation.inverse().to_homogeneous()
+            * Matrix4::new_translation(&-translation);
+        (matrix, inverse)
+    }
+}
+
+impl<H: Hittable> Hittable for AnimatedTransform<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (matrix, inverse) = self.matrices(ray.time());
+        hit_placed(&self.hittable, &matrix, &inverse, ray, t_min, t_max)
+    }
+
+    /// Follows the corners of the object's box through the shutter interval.
+    /// Between keyframes a corner moves along the chord of its samples plus
+    /// a bend from the rotation; the box is padded by a bound on that bend.
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let bbox = self.hittable.bounding_box(t0, t1)?;
+        let corners: Vec<Vector3<f32>> = (0..8)
+            .map(|i| {
+                Vector3::new(
+                    if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+                    if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+                    if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+                )
+            })
+            .collect();
+        let mut times = vec![t0];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|&t| t > t0 && t < t1),
+        );
+        times.push(t1);
+        // Corners in world space and after scaling alone.
+        let place = |time: f32| {
+            let (translation, rotation, scale) = self.pose(time);
+            let scaled: Vec<_> = corners.iter().map(|c| c.component_mul(&scale)).collect();
+            let placed: Vec<_> = scaled.iter().map(|c| translation + rotation * c).collect();
+            (placed, scaled)
+        };
+        let mut result = AABB::new(Vector3::repeat(f32::MAX), Vector3::repeat(-f32::MAX));
+        let mut add = |points: &[Vector3<f32>], pad: f32| {
+            let pad = Vector3::repeat(pad);
+            for p in points {
+                result = aabb::surrounding_box(&result, &AABB::new(p - pad, p + pad));
+            }
+        };
+        add(&place(t0).0, 0.0);
+        for segment in times.windows(2) {
+            // Rotation turns at a constant rate between keyframes.
+            let (a, b) = self.keys(0.5 * (segment[0] + segment[1]));
+            let angle = if a.time == b.time {
+                0.0
+            } else {
+                turn(a, b) * (segment[1] - segment[0]) / (b.time - a.time)
+            };
+            let steps = (angle / MAX_BOUNDS_STEP).ceil().max(1.0) as usize;
+            let theta = angle / steps as f32;
+            let mut previous = place(segment[0]);
+            for step in 1..=steps {
+                let time = segment[0] + (segment[1] - segment[0]) * step as f32 / steps as f32;
+                let current = place(time);
+                // A corner r(t) = R(t) S(t) c strays from the chord between
+                // two samples by at most dt^2 / 8 max |r''|, with |r''| <=
+                // w^2 |S c| + 2 w |S' c| for the angular speed w.
+                let pad = current
+                    .1
+                    .iter()
+                    .zip(&previous.1)
+                    .map(|(c, p)| {
+                        theta.powi(2) / 8.0 * c.norm().max(p.norm()) + theta / 4.0 * (c - p).norm()
+                    })
+                    .fold(0.0, f32::max);
+                add(&previous.0, pad);
+                add(&current.0, pad);
+                previous = current;
+            }
+        }
+        Some(result)
+    }
+}
@@ -0,0 +1,184 @@
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::pdf::PDF;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use nalgebra::Vector3;
+
+// Step in u and v over which height fields are differentiated.
+const DELTA: f32 = 0.001;
+
+/// `hit` with the shading normal `normal`, or unchanged if the tangent
+/// frame is degenerate or the ray would see the shading side from behind.
+/// The shading normal is turned to the side of the geometric one.
+fn shade<'a>(ray: &Ray, hit: &HitRecord<'a>, normal: Vector3<f32>) -> HitRecord<'a> {
+    let Some(normal) = normal.try_normalize(1e-12) else {
+        return *hit;
+    };
+    let normal = if normal.dot(&hit.normal) < 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    if normal.dot(&ray.direction()) * hit.normal.dot(&ray.direction()) <= 0.0 {
+        return *hit;
+    }
+    HitRecord {
+        shading_normal: normal,
+        ..*hit
+    }
+}
+
+/// Scatters with `material` off `shading`, dropping specular paths that
+/// leak through the surface.
+fn scatter_shaded<'a>(
+    material: &'a dyn Material,
+    ray: &Ray,
+    shading: &HitRecord,
+    rng: &mut Sampler,
+) -> Option<ScatterRecord<'a>> {
+    material
+        .scatter(ray, shading, rng)
+        .filter(|scatter| match scatter {
+            ScatterRecord::Specular { specular_ray, .. } => {
+                shading.consistent(&specular_ray.direction())
+            }
+            ScatterRecord::Scatter { .. } => true,
+        })
+}
+
+/// Scattering of `material` off `shading` towards `scattered`, which is zero
+/// where it leaks through the surface.
+fn scattering_shaded(
+    material: &dyn Material,
+    ray: &Ray,
+    shading: &HitRecord,
+    scattered: &Ray,
+) -> Vector3<f32> {
+    if shading.consistent(&scattered.direction()) {
+        material.scattering(ray, shading, scattered)
+    } else {
+        Vector3::zeros()
+    }
+}
+
+/// Perturbs the shading normal of `material` with a tangent space normal
+/// map, whose red, green and blue run along dpdu, its complement in the
+/// tangent plane and the normal, mapped from [0, 1] to [-1, 1].
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct NormalMap<M: Material, T: Texture> {
+    material: M,
+    map: T,
+}
+
+#[allow(dead_code)]
+impl<M: Material, T: Texture> NormalMap<M, T> {
+    pub fn new(material: M, map: T) -> Self {
+        NormalMap { material, map }
+    }
+
+    fn shading<'a>(&self, ray: &Ray, hit: &HitRecord<'a>) -> HitRecord<'a> {
+        let n = hit.shading_normal;
+        let Some(tangent) = (hit.dpdu - n * n.dot(&hit.dpdu)).try_normalize(1e-12) else {
+            return *hit;
+        };
+        let bitangent = n.cross(&tangent);
+        let bitangent = if bitangent.dot(&hit.dpdv) < 0.0 {
+            -bitangent
+        } else {
+            bitangent
+        };
+        let m = 2.0 * self.map.value(hit.u, hit.v, &hit.p) - Vector3::repeat(1.0);
+        shade(ray, hit, m.x * tangent + m.y * bitangent + m.z * n)
+    }
+}
+
+impl<M: Material, T: Texture> Material for NormalMap<M, T> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        let shading = self.shading(ray, hit);
+        scatter_shaded(&self.material, ray, &shading, rng)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        let shading = self.shading(ray, hit);
+        self.material.scattering_pdf(ray, &shading, scattered)
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        let shading = self.shading(ray, hit);
+        scattering_shaded(&self.material, ray, &shading, scattered)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        let shading = self.shading(ray, hit);
+        self.material.scatter_pdf(ray, &shading)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+        self.material.emitted(ray, hit)
+    }
+}
+
+/// Perturbs the shading normal of `material` as if the surface were
+/// displaced along its normal by `scale` times the first channel of
+/// `height`.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Bump<M: Material, T: Texture> {
+    material: M,
+    height: T,
+    scale: f32,
+}
+
+#[allow(dead_code)]
+impl<M: Material, T: Texture> Bump<M, T> {
+    pub fn new(material: M, height: T, scale: f32) -> Self {
+        Bump {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn shading<'a>(&self, ray: &Ray, hit: &HitRecord<'a>) -> HitRecord<'a> {
+        // Height at an offset in uv, where the point moves along the
+        // tangents for textures that depend on it.
+        let height = |du: f32, dv: f32| {
+            let p = hit.p + du * hit.dpdu + dv * hit.dpdv;
+            self.scale * self.height.value(hit.u + du, hit.v + dv, &p).x
+        };
+        let dhdu = (height(DELTA, 0.0) - height(-DELTA, 0.0)) / (2.0 * DELTA);
+        let dhdv = (height(0.0, DELTA) - height(0.0, -DELTA)) / (2.0 * DELTA);
+        let dpdu = hit.dpdu + dhdu * hit.shading_normal;
+        let dpdv = hit.dpdv + dhdv * hit.shading_normal;
+        shade(ray, hit, dpdu.cross(&dpdv))
+    }
+}
+
+impl<M: Material, T: Texture> Material for Bump<M, T> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        let shading = self.shading(ray, hit);
+        scatter_shaded(&self.material, ray, &shading, rng)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        let shading = self.shading(ray, hit);
+        self.material.scattering_pdf(ray, &shading, scattered)
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        let shading = self.shading(ray, hit);
+        scattering_shaded(&self.material, ray, &shading, scattered)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        let shading = self.shading(ray, hit);
+        self.material.scatter_pdf(ray, &shading)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+        self.material.emitted(ray, hit)
+    }
+}
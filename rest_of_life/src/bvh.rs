@@ -0,0 +1,126 @@
+use crate::aabb;
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f32;
+
+enum BVHNode {
+    Branch { left: Box<BVH>, right: Box<BVH> },
+    Leaf(Box<dyn Hittable>),
+}
+
+/// Bounding volume hierarchy. As a light group it samples like a
+/// `HittableList` of the same objects: `random` picks every leaf with equal
+/// probability and `pdf_value` is the average of the leaf densities.
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVH {
+    tree: BVHNode,
+    bbox: AABB,
+    leaves: usize,
+}
+
+impl BVH {
+    pub fn new(hittable: Vec<Box<dyn Hittable>>, time0: f32, time1: f32) -> Self {
+        let mut hittable: Vec<(AABB, Box<dyn Hittable>)> = hittable
+            .into_iter()
+            .map(|h| match h.bounding_box(time0, time1) {
+                Some(bbox) => (bbox, h),
+                None => panic!["no bounding box in bvh node"],
+            })
+            .collect();
+        if hittable.is_empty() {
+            panic!["no elements in scene"]
+        }
+        BVH::build(&mut hittable)
+    }
+
+    fn build(hittable: &mut Vec<(AABB, Box<dyn Hittable>)>) -> Self {
+        let centroid = |(bbox, _): &(AABB, Box<dyn Hittable>)| bbox.min + bbox.max;
+        let (min, max) = hittable.iter().map(centroid).fold(
+            (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+            |(min, max), c| (min.inf(&c), max.sup(&c)),
+        );
+        let axis = (max - min).imax();
+        hittable.sort_unstable_by(|a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+        let len = hittable.len();
+        if len == 1 {
+            let (bbox, leaf) = hittable.pop().unwrap();
+            BVH {
+                tree: BVHNode::Leaf(leaf),
+                bbox,
+                leaves: 1,
+            }
+        } else {
+            let right = BVH::build(&mut hittable.drain(len / 2..).collect());
+            let left = BVH::build(hittable);
+            BVH {
+                bbox: aabb::surrounding_box(&left.bbox, &right.bbox),
+                leaves: left.leaves + right.leaves,
+                tree: BVHNode::Branch {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            }
+        }
+    }
+
+    /// Sum of the leaf densities. Subtrees whose box the direction misses
+    /// cannot contribute and are skipped.
+    fn pdf_sum(&self, ray: &Ray) -> f32 {
+        if !self.bbox.hit(ray, 0.001, f32::MAX) {
+            return 0.0;
+        }
+        match &self.tree {
+            BVHNode::Leaf(leaf) => leaf.pdf_value(ray.origin(), ray.direction()),
+            BVHNode::Branch { left, right } => left.pdf_sum(ray) + right.pdf_sum(ray),
+        }
+    }
+}
+
+impl Hittable for BVH {
+    fn hit(&self, ray: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord<'_>> {
+        if self.bbox.hit(ray, t_min, t_max) {
+            match &self.tree {
+                BVHNode::Leaf(leaf) => leaf.hit(ray, t_min, t_max),
+                BVHNode::Branch { left, right } => {
+                    let left = left.hit(ray, t_min, t_max);
+                    if let Some(l) = &left {
+                        t_max = l.t
+                    };
+                    let right = right.hit(ray, t_min, t_max);
+                    if right.is_some() {
+                        right
+                    } else {
+                        left
+                    }
+                }
+            }
+        } else {
+            None
+        }
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
+        self.pdf_sum(&Ray::new(o, v, 0.0)) / self.leaves as f32
+    }
+
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        match &self.tree {
+            BVHNode::Leaf(leaf) => leaf.random(o, rng),
+            BVHNode::Branch { left, right } => {
+                if rng.gen_range(0..self.leaves) < left.leaves {
+                    left.random(o, rng)
+                } else {
+                    right.random(o, rng)
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,10 @@
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 use std::f32;
 
-fn random_in_unit_disk() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+fn random_in_unit_disk(rng: &mut Sampler) -> Vector3<f32> {
     let unit = Vector3::new(1.0, 1.0, 0.0);
     loop {
         let p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0) - unit;
@@ -27,6 +27,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vector3<f32>,
         look_at: Vector3<f32>,
@@ -57,15 +58,15 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
+    pub fn get_ray(&self, s: f32, t: f32, rng: &mut Sampler) -> Ray {
         let origin = if self.lens_radius == 0.0 {
             self.origin
         } else {
-            let rd = self.lens_radius * random_in_unit_disk();
+            let rd = self.lens_radius * random_in_unit_disk(rng);
             let offset = self.u * rd.x + self.v * rd.y;
             self.origin + offset
         };
-        let time = self.time0 + rand::thread_rng().gen::<f32>() * (self.time1 - self.time0);
+        let time = self.time0 + rng.gen::<f32>() * (self.time1 - self.time0);
         Ray::new(
             origin,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - origin,
@@ -0,0 +1,178 @@
+use crate::film::{Film, Pixel};
+use nalgebra::Vector3;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTCKPT02";
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(input: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(input)?))
+}
+
+/// Saves the accumulated film, the sampling seed and a description of the
+/// scene options to `path`. The file is written next to `path` first and then
+/// renamed over it, so an interrupted write never destroys the previous
+/// checkpoint.
+///
+/// Layout, all little-endian: the magic `RTCKPT02`, width and height as u32,
+/// the seed as u64, the byte length of the scene description as u32 and its
+/// UTF-8 text, then for every pixel from the top left the radiance sum as
+/// three f32, the squared luminance sum as f32 and the sample count as u32.
+pub fn save(path: &Path, film: &Film, seed: u64, scene: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&film.width().to_le_bytes())?;
+    out.write_all(&film.height().to_le_bytes())?;
+    out.write_all(&seed.to_le_bytes())?;
+    out.write_all(&(scene.len() as u32).to_le_bytes())?;
+    out.write_all(scene.as_bytes())?;
+    for pixel in film.data() {
+        for c in pixel.sum.iter().chain(std::iter::once(&pixel.sum_sq)) {
+            out.write_all(&c.to_le_bytes())?;
+        }
+        out.write_all(&pixel.samples.to_le_bytes())?;
+    }
+    out.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Loads a checkpoint written by `save`, returning the film and its seed.
+/// Checkpoints rendered with another scene description than `scene` are
+/// refused.
+pub fn load(path: &Path, scene: &str) -> io::Result<(Film, u64)> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid(format!(
+            "'{}' is not a render checkpoint",
+            path.display()
+        )));
+    }
+    let width = read_u32(&mut input)?;
+    let height = read_u32(&mut input)?;
+    let seed = read_u64(&mut input)?;
+    let mut saved_scene = vec![0; read_u32(&mut input)? as usize];
+    input.read_exact(&mut saved_scene)?;
+    let saved_scene = String::from_utf8(saved_scene).map_err(|_| {
+        invalid(format!(
+            "invalid scene description in checkpoint '{}'",
+            path.display()
+        ))
+    })?;
+    if saved_scene != scene {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "checkpoint '{}' was rendered with {}, not {}",
+                path.display(),
+                saved_scene,
+                scene
+            ),
+        ));
+    }
+    let mut data = Vec::with_capacity(width as usize * height as usize);
+    for _ in 0..width as usize * height as usize {
+        let sum = Vector3::new(
+            read_f32(&mut input)?,
+            read_f32(&mut input)?,
+            read_f32(&mut input)?,
+        );
+        data.push(Pixel {
+            sum,
+            sum_sq: read_f32(&mut input)?,
+            samples: read_u32(&mut input)?,
+        });
+    }
+    if input.read(&mut [0])? != 0 {
+        return Err(invalid(format!(
+            "trailing data in checkpoint '{}'",
+            path.display()
+        )));
+    }
+    Ok((Film::from_data(width, height, data), seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Checkpoint path in the temporary directory, unique to `name` and this
+    /// process.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("checkpoint-{}-{}", std::process::id(), name))
+    }
+
+    /// A 3x2 film whose pixels all differ.
+    fn film() -> Film {
+        let pixels = (0..6)
+            .map(|i| {
+                let mut pixel = Pixel::default();
+                for n in 0..=i {
+                    pixel.add_sample(Vector3::new(0.1 * n as f32, 0.5, i as f32));
+                }
+                pixel
+            })
+            .collect();
+        Film::from_data(3, 2, pixels)
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = temp_path("round-trip");
+        let saved = film();
+        save(&path, &saved, 42, "scene a").unwrap();
+        let loaded = load(&path, "scene a");
+        fs::remove_file(&path).unwrap();
+        let (loaded, seed) = loaded.unwrap();
+        assert_eq!(seed, 42);
+        assert_eq!((loaded.width(), loaded.height()), (3, 2));
+        for (a, b) in saved.data().iter().zip(loaded.data()) {
+            assert_eq!(a.sum, b.sum);
+            assert_eq!(a.sum_sq, b.sum_sq);
+            assert_eq!(a.samples, b.samples);
+        }
+    }
+
+    #[test]
+    fn refuses_other_files() {
+        let path = temp_path("magic");
+        fs::write(&path, b"P6\n3 2\n255\n").unwrap();
+        let error = load(&path, "scene a").err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn refuses_other_scenes() {
+        let path = temp_path("scene");
+        save(&path, &film(), 42, "scene a").unwrap();
+        let error = load(&path, "scene b").err();
+        fs::remove_file(&path).unwrap();
+        let error = error.unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error
+            .to_string()
+            .contains("rendered with scene a, not scene b"));
+    }
+}
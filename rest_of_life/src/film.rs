@@ -0,0 +1,124 @@
+use crate::render::Tile;
+use crate::tonemap::luminance;
+use nalgebra::Vector3;
+
+/// Running statistics of the radiance samples taken for one pixel.
+#[derive(Clone, Copy)]
+pub struct Pixel {
+    pub sum: Vector3<f32>,
+    /// Sum of squared sample luminances, for the variance estimate.
+    pub sum_sq: f32,
+    pub samples: u32,
+}
+
+impl Default for Pixel {
+    fn default() -> Self {
+        Pixel {
+            sum: Vector3::zeros(),
+            sum_sq: 0.0,
+            samples: 0,
+        }
+    }
+}
+
+impl Pixel {
+    pub fn add_sample(&mut self, color: Vector3<f32>) {
+        self.sum += color;
+        self.sum_sq += luminance(&color).powi(2);
+        self.samples += 1;
+    }
+
+    pub fn mean(&self) -> Vector3<f32> {
+        if self.samples > 0 {
+            self.sum / self.samples as f32
+        } else {
+            Vector3::zeros()
+        }
+    }
+
+    /// Standard error of the mean luminance relative to the mean itself.
+    /// Dark pixels are measured against a small floor so that a black
+    /// background converges instead of dividing by zero.
+    pub fn relative_error(&self) -> f32 {
+        if self.samples < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.samples as f32;
+        let mean = luminance(&self.sum) / n;
+        let variance = ((self.sum_sq - n * mean.powi(2)) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.max(1e-3)
+    }
+}
+
+/// Floating-point accumulation buffer with per-pixel sample statistics,
+/// rows from the top.
+#[derive(Clone)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![Pixel::default(); width as usize * height as usize],
+        }
+    }
+
+    /// Film made of previously accumulated pixels, rows from the top.
+    pub fn from_data(width: u32, height: u32, data: Vec<Pixel>) -> Self {
+        assert_eq!(data.len(), width as usize * height as usize);
+        Film {
+            width,
+            height,
+            pixels: data,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn tile_rows(&self, tile: &Tile) -> impl Iterator<Item = std::ops::Range<usize>> {
+        let width = self.width as usize;
+        let (x0, x1) = (tile.x0 as usize, tile.x1 as usize);
+        (tile.y0 as usize..tile.y1 as usize).map(move |y| y * width + x0..y * width + x1)
+    }
+
+    /// Copy of the pixels covered by `tile`, rows from the top.
+    pub fn tile(&self, tile: &Tile) -> Vec<Pixel> {
+        self.tile_rows(tile)
+            .flat_map(|row| self.pixels[row].iter().copied())
+            .collect()
+    }
+
+    /// Stores pixels previously taken out with `tile`.
+    pub fn set_tile(&mut self, tile: &Tile, pixels: &[Pixel]) {
+        let rows: Vec<_> = self.tile_rows(tile).collect();
+        for (row, src) in rows.into_iter().zip(pixels.chunks(tile.width() as usize)) {
+            self.pixels[row].copy_from_slice(src);
+        }
+    }
+
+    /// Accumulated statistics of every pixel, rows from the top.
+    pub fn data(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// Mean radiance of every pixel, rows from the top.
+    pub fn pixels(&self) -> Vec<Vector3<f32>> {
+        self.pixels.iter().map(Pixel::mean).collect()
+    }
+
+    /// Number of samples taken for every pixel, rows from the top.
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(|p| p.samples).collect()
+    }
+}
@@ -2,25 +2,44 @@ use crate::aabb;
 use crate::aabb::AABB;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::seq::SliceRandom;
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub t: f32,
     pub u: f32,
     pub v: f32,
     pub p: Vector3<f32>,
+    /// Geometric normal, which tells the sides of the surface apart.
     pub normal: Vector3<f32>,
+    /// Normal that materials shade with, on the side of `normal`. Differs
+    /// from it where a mesh interpolates vertex normals or a bump map tilts it.
+    pub shading_normal: Vector3<f32>,
+    /// Derivatives of the position by u and v, which span the tangent plane.
+    /// Zero where the surface has no parameterization.
+    pub dpdu: Vector3<f32>,
+    pub dpdv: Vector3<f32>,
     pub material: &'a dyn Material,
 }
 
+impl HitRecord<'_> {
+    /// Whether `direction` is on the same side of the geometric and the
+    /// shading normal. Light going anywhere else would pass through the
+    /// surface.
+    pub fn consistent(&self, direction: &Vector3<f32>) -> bool {
+        self.normal.dot(direction) * self.shading_normal.dot(direction) > 0.0
+    }
+}
+
 pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
     fn pdf_value(&self, _o: Vector3<f32>, _v: Vector3<f32>) -> f32 {
         0.0
     }
-    fn random(&self, _o: Vector3<f32>) -> Vector3<f32> {
+    fn random(&self, _o: Vector3<f32>, _rng: &mut Sampler) -> Vector3<f32> {
         Vector3::new(1.0, 0.0, 0.0)
     }
 }
@@ -37,7 +56,7 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut closest_so_far = t_max;
         let mut hit_anything: Option<HitRecord> = None;
         for h in self.list.iter() {
@@ -67,8 +86,8 @@ impl Hittable for HittableList {
         self.list.iter().map(|h| h.pdf_value(o, v)).sum::<f32>() / self.list.len() as f32
     }
 
-    fn random(&self, o: Vector3<f32>) -> Vector3<f32> {
-        self.list.choose(&mut rand::thread_rng()).unwrap().random(o)
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        self.list.choose(rng).unwrap().random(o, rng)
     }
 }
 
@@ -83,9 +102,10 @@ impl<H: Hittable> FlipNormals<H> {
 }
 
 impl<H: Hittable> Hittable for FlipNormals<H> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         self.hittable.hit(ray, t_min, t_max).map(|mut hit| {
             hit.normal = -hit.normal;
+            hit.shading_normal = -hit.shading_normal;
             hit
         })
     }
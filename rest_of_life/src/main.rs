@@ -1,100 +1,356 @@
 mod aabb;
+mod bump;
+mod bvh;
 mod camera;
+mod checkpoint;
 mod cube;
+mod film;
 mod hittable;
 mod material;
+mod microfacet;
+mod obj;
 mod onb;
+mod output;
 mod pdf;
+mod ply;
+mod quadric;
 mod ray;
 mod rect;
-mod rotate;
+mod render;
+mod sampler;
 mod sphere;
 mod texture;
-mod translate;
+mod tonemap;
+mod transform;
+mod triangle;
 
+use crate::bvh::BVH;
 use crate::camera::Camera;
 use crate::cube::Cube;
-use crate::hittable::{FlipNormals, Hittable, HittableList};
-use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, ScatterRecord};
+use crate::film::Film;
+use crate::hittable::{FlipNormals, Hittable};
+use crate::material::{
+    Coated, Conductor, Dielectric, DiffuseLight, Lambertian, Material, Metal, MixMaterial,
+    Principled, RoughDielectric, ScatterRecord,
+};
 use crate::pdf::PDF;
+use crate::quadric::{Cylinder, Disk};
 use crate::ray::Ray;
-use crate::rect::{AARect, Plane};
-use crate::rotate::{Axis, Rotate};
+use crate::rect::{AARect, Plane, Quad};
+use crate::render::Renderer;
+use crate::sampler::Sampler;
 use crate::sphere::Sphere;
 use crate::texture::ConstantTexture;
-use crate::translate::Translate;
-use nalgebra::Vector3;
+use crate::tonemap::{Operator, ToneMapper};
+use crate::transform::Transform;
+use crate::triangle::Mesh;
+use clap::{Parser, ValueEnum};
+use nalgebra::{Matrix4, Vector3};
 use rand::Rng;
-use rayon::prelude::*;
 use std::f32;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
 const MAX_DEPTH: i32 = 1000;
 
-fn cornell_box(aspect: f32) -> (Box<dyn Hittable>, Box<dyn Hittable>, Camera) {
-    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
-    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
-    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
-    let light = DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0));
-    let glass = Dielectric::new(1.5);
-    let aluminum = Metal::new(Vector3::new(0.8, 0.85, 0.88), 0.0);
-    let light_shape = AARect::new(Plane::ZX, 227.0, 332.0, 213.0, 343.0, 554.0, light);
-    let glass_sphere = Sphere::new(Vector3::new(190.0, 90.0, 190.0), 90.0, glass);
-    let mut world = HittableList::default();
-    world.push(FlipNormals::new(AARect::new(
-        Plane::YZ,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        green,
-    )));
-    world.push(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red));
-    world.push(FlipNormals::new(light_shape.clone()));
-    world.push(FlipNormals::new(AARect::new(
-        Plane::ZX,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        white.clone(),
-    )));
-    world.push(AARect::new(
-        Plane::ZX,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        0.0,
-        white.clone(),
-    ));
-    world.push(FlipNormals::new(AARect::new(
-        Plane::XY,
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-        white.clone(),
-    )));
-    world.push(glass_sphere.clone());
-    world.push(Translate::new(
-        Rotate::new(
-            Axis::Y,
-            Cube::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(165.0, 330.0, 165.0),
-                aluminum,
+#[derive(Parser)]
+#[command(about = "Ray Tracing: The Rest of Your Life")]
+struct Args {
+    /// Image width in pixels
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+    /// Image height in pixels
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+    /// Samples per pixel, the upper limit when sampling adaptively
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    samples: u32,
+    /// Stop sampling a pixel once the relative standard error of its
+    /// luminance drops below this value
+    #[arg(long)]
+    threshold: Option<f32>,
+    /// Samples every pixel takes before the adaptive threshold is checked
+    #[arg(long, default_value_t = 16)]
+    min_samples: u32,
+    /// Also write an image of the number of samples taken per pixel
+    #[arg(long)]
+    sample_map: Option<PathBuf>,
+    /// Output file (.png, .ppm, .pfm or .exr), binary PPM on stdout if omitted
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Tone mapping operator for 8-bit output
+    #[arg(long, value_enum, default_value = "clamp")]
+    tonemap: Operator,
+    /// Exposure adjustment in stops
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f32,
+    /// Luminance mapped to white by the extended Reinhard operator
+    #[arg(long, default_value_t = 4.0)]
+    white: f32,
+    /// Seed for sampling; equal seeds give identical images
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Edge length of the square render tiles in pixels
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    tile_size: u32,
+    /// Do not report progress on stderr
+    #[arg(short, long)]
+    quiet: bool,
+    /// Periodically save the accumulated film to this file
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Seconds between checkpoint saves
+    #[arg(long, default_value_t = 300)]
+    checkpoint_interval: u64,
+    /// Continue from the checkpoint file, with its seed, instead of starting
+    /// from scratch
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+    /// Shape of the lamp
+    #[arg(long, value_enum, default_value = "rect")]
+    lamp: Lamp,
+    /// Make the aluminium box a GGX conductor of this roughness, 0 for a
+    /// mirror, instead of a plain metal
+    #[arg(long)]
+    roughness: Option<f32>,
+    /// Make the glass sphere a rough dielectric of this GGX roughness, 0 for
+    /// clear glass, instead of a plain dielectric
+    #[arg(long)]
+    glass_roughness: Option<f32>,
+    /// Build the box from the principled material instead
+    #[arg(long)]
+    principled: bool,
+    /// Build the box from mixed and clear coated materials instead
+    #[arg(long, conflicts_with = "principled")]
+    layered: bool,
+    /// Add the triangles of this OBJ or PLY file, in world coordinates, to the scene
+    #[arg(long)]
+    mesh: Option<PathBuf>,
+    /// Number of render threads, all cores if omitted
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+}
+
+/// Shape of the Cornell box lamp.
+#[derive(Clone, Copy, ValueEnum)]
+enum Lamp {
+    /// The original rectangle in the ceiling
+    Rect,
+    /// A disk of about the same area in the ceiling
+    Disk,
+    /// A closed cylinder hanging below the ceiling
+    Cylinder,
+    /// A panel below the ceiling tilted towards the camera
+    Quad,
+}
+
+type SharedMaterial = Arc<dyn Material + Send>;
+
+/// Materials of the Cornell box.
+struct Palette {
+    red: SharedMaterial,
+    white: SharedMaterial,
+    green: SharedMaterial,
+    light: SharedMaterial,
+    aluminium: SharedMaterial,
+    glass: SharedMaterial,
+}
+
+impl Palette {
+    fn classic(roughness: Option<f32>, glass_roughness: Option<f32>) -> Self {
+        let lambertian = |r, g, b| Arc::new(Lambertian::new(ConstantTexture::new(r, g, b)));
+        Palette {
+            red: lambertian(0.65, 0.05, 0.05),
+            white: lambertian(0.73, 0.73, 0.73),
+            green: lambertian(0.12, 0.45, 0.15),
+            light: Arc::new(DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0))),
+            aluminium: match roughness {
+                Some(roughness) => Arc::new(Conductor::named("aluminium", roughness).unwrap()),
+                None => Arc::new(Metal::new(Vector3::new(0.8, 0.85, 0.88), 0.0)),
+            },
+            glass: match glass_roughness {
+                Some(roughness) => Arc::new(RoughDielectric::new(1.5, roughness)),
+                None => Arc::new(Dielectric::new(1.5)),
+            },
+        }
+    }
+
+    /// Lacquered colored walls and a box of aluminium partly rusted over.
+    fn layered(roughness: Option<f32>, glass_roughness: Option<f32>) -> Self {
+        let classic = Palette::classic(roughness, glass_roughness);
+        let lacquered = |r, g, b| {
+            Arc::new(Coated::new(
+                Lambertian::new(ConstantTexture::new(r, g, b)),
+                1.5,
+                0.1,
+            ))
+        };
+        Palette {
+            red: lacquered(0.65, 0.05, 0.05),
+            green: lacquered(0.12, 0.45, 0.15),
+            aluminium: Arc::new(MixMaterial::new(
+                Conductor::named("aluminium", roughness.unwrap_or(0.0)).unwrap(),
+                Lambertian::new(ConstantTexture::new(0.45, 0.2, 0.08)),
+                ConstantTexture::new(0.4, 0.4, 0.4),
+            )),
+            ..classic
+        }
+    }
+
+    /// The same scene from the principled material, with walls of cloth, a
+    /// clear coated aluminium box and a glass sphere.
+    fn principled(roughness: Option<f32>, glass_roughness: Option<f32>) -> Self {
+        let roughness = roughness.unwrap_or(0.0);
+        let glass_roughness = glass_roughness.unwrap_or(0.0);
+        let cloth = |r, g, b| {
+            Arc::new(
+                Principled::new(ConstantTexture::new(r, g, b))
+                    .roughness(ConstantTexture::new(1.0, 1.0, 1.0))
+                    .specular(ConstantTexture::new(0.0, 0.0, 0.0))
+                    .sheen(ConstantTexture::new(r, g, b)),
+            )
+        };
+        let white = || ConstantTexture::new(1.0, 1.0, 1.0);
+        Palette {
+            red: cloth(0.65, 0.05, 0.05),
+            white: cloth(0.73, 0.73, 0.73),
+            green: cloth(0.12, 0.45, 0.15),
+            light: Arc::new(
+                Principled::new(ConstantTexture::new(0.0, 0.0, 0.0))
+                    .specular(ConstantTexture::new(0.0, 0.0, 0.0))
+                    .emission(ConstantTexture::new(15.0, 15.0, 15.0)),
+            ),
+            aluminium: Arc::new(
+                Principled::new(ConstantTexture::new(0.91, 0.92, 0.92))
+                    .metallic(white())
+                    .roughness(ConstantTexture::new(roughness, roughness, roughness))
+                    .clearcoat(white()),
             ),
-            15.0,
+            glass: Arc::new(
+                Principled::new(white())
+                    .transmission(white())
+                    .roughness(ConstantTexture::new(
+                        glass_roughness,
+                        glass_roughness,
+                        glass_roughness,
+                    ))
+                    .ior(1.5),
+            ),
+        }
+    }
+}
+
+/// The lamp as part of the world and as a shape to sample.
+fn lamp(lamp: Lamp, light: SharedMaterial) -> (Box<dyn Hittable>, Box<dyn Hittable>) {
+    match lamp {
+        Lamp::Rect => {
+            let shape = AARect::new(Plane::ZX, 227.0, 332.0, 213.0, 343.0, 554.0, light);
+            (Box::new(FlipNormals::new(shape.clone())), Box::new(shape))
+        }
+        Lamp::Disk => {
+            let center = Vector3::new(279.5, 554.0, 278.0);
+            (
+                Box::new(FlipNormals::new(Disk::new(center, 65.0, light.clone()))),
+                Box::new(Disk::new(center, 65.0, light)),
+            )
+        }
+        Lamp::Cylinder => {
+            let center = Vector3::new(279.5, 444.0, 278.0);
+            (
+                Box::new(Cylinder::new(center, 20.0, 80.0, true, light.clone())),
+                Box::new(Cylinder::new(center, 20.0, 80.0, true, light)),
+            )
+        }
+        Lamp::Quad => {
+            let corner = Vector3::new(213.0, 540.0, 227.0);
+            let u = Vector3::new(130.0, 0.0, 0.0);
+            let v = Vector3::new(0.0, -60.0, 90.0);
+            (
+                Box::new(Quad::new(corner, u, v, light.clone())),
+                Box::new(Quad::new(corner, u, v, light)),
+            )
+        }
+    }
+}
+
+fn cornell_box(
+    aspect: f32,
+    lamp: Lamp,
+    palette: Palette,
+    mesh: Option<Mesh>,
+) -> (Box<dyn Hittable>, Box<dyn Hittable>, Camera) {
+    let Palette {
+        red,
+        white,
+        green,
+        light,
+        aluminium,
+        glass,
+    } = palette;
+    let (lamp, light_shape) = self::lamp(lamp, light);
+    let glass_sphere = Sphere::new(Vector3::new(190.0, 90.0, 190.0), 90.0, glass);
+    let mut world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::YZ,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            green,
+        ))),
+        Box::new(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red)),
+        lamp,
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        ))),
+        Box::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            0.0,
+            white.clone(),
+        )),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::XY,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        ))),
+        Box::new(glass_sphere.clone()),
+        Box::new(
+            Transform::rotate(
+                Cube::new(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    Vector3::new(165.0, 330.0, 165.0),
+                    aluminium,
+                ),
+                Vector3::y(),
+                15.0,
+            )
+            .then(Matrix4::new_translation(&Vector3::new(265.0, 0.0, 295.0))),
         ),
-        Vector3::new(265.0, 0.0, 295.0),
-    ));
+    ];
+
+    if let Some(mesh) = mesh {
+        world.push(Box::new(mesh));
+    }
 
-    let mut light_shapes = HittableList::default();
-    light_shapes.push(light_shape);
-    light_shapes.push(glass_sphere);
+    let light_shapes: Vec<Box<dyn Hittable>> = vec![light_shape, Box::new(glass_sphere)];
 
     let look_from = Vector3::new(278.0, 278.0, -800.0);
     let look_at = Vector3::new(278.0, 278.0, 0.0);
@@ -113,42 +369,53 @@ fn cornell_box(aspect: f32) -> (Box<dyn Hittable>, Box<dyn Hittable>, Camera) {
         1.0,
     );
 
-    (Box::new(world), Box::new(light_shapes), cam)
+    (
+        Box::new(BVH::new(world, 0.0, 1.0)),
+        Box::new(BVH::new(light_shapes, 0.0, 1.0)),
+        cam,
+    )
 }
 
 fn color(
     ray: &Ray,
-    world: &Box<dyn Hittable>,
-    light_shape: &Box<dyn Hittable>,
+    world: &dyn Hittable,
+    light_shape: &dyn Hittable,
     depth: i32,
+    rng: &mut Sampler,
 ) -> Vector3<f32> {
+    render::count_ray();
     if let Some(hit) = world.hit(ray, 0.001, f32::MAX) {
         let emitted = hit.material.emitted(ray, &hit);
         if depth < MAX_DEPTH {
-            if let Some(scatter) = hit.material.scatter(ray, &hit) {
+            if let Some(scatter) = hit.material.scatter(ray, &hit, rng) {
                 match scatter {
                     ScatterRecord::Specular {
                         specular_ray,
                         attenuation,
-                    } => {
+                    } if hit.consistent(&specular_ray.direction()) => {
                         return attenuation.zip_map(
-                            &color(&specular_ray, world, light_shape, depth + 1),
+                            &color(&specular_ray, world, light_shape, depth + 1, rng),
                             |l, r| l * r,
                         )
                     }
                     ScatterRecord::Scatter { pdf, attenuation } => {
                         let hittable_pdf = PDF::hittable(light_shape, hit.p);
                         let pdf_fun = PDF::mixture(&hittable_pdf, &pdf);
-                        let scattered = Ray::new(hit.p, pdf_fun.generate(), ray.time());
+                        let scattered = Ray::new(hit.p, pdf_fun.generate(rng), ray.time());
                         let pdf_val = pdf_fun.value(scattered.direction());
-                        let scattering_pdf = hit.material.scattering_pdf(ray, &hit, &scattered);
+                        if pdf_val <= 0.0 || !hit.consistent(&scattered.direction()) {
+                            return emitted;
+                        }
+                        let scattering = hit.material.scattering(ray, &hit, &scattered);
                         return emitted
-                            + attenuation.zip_map(
-                                &(scattering_pdf
-                                    * color(&scattered, world, light_shape, depth + 1)),
+                            + attenuation.component_mul(&scattering).zip_map(
+                                &color(&scattered, world, light_shape, depth + 1, rng),
                                 |l, r| l * r,
                             ) / pdf_val;
                     }
+                    // Paths leaking through a surface with a tilted shading
+                    // normal end.
+                    ScatterRecord::Specular { .. } => {}
                 }
             }
         }
@@ -158,35 +425,127 @@ fn color(
     }
 }
 
+/// The options that change what is rendered, which a checkpoint must have
+/// been rendered with to be resumed.
+fn scene_options(args: &Args) -> String {
+    let palette = if args.principled {
+        "principled"
+    } else if args.layered {
+        "layered"
+    } else {
+        "classic"
+    };
+    format!(
+        "lamp {}, roughness {}, glass roughness {}, {} materials, mesh {}",
+        args.lamp.to_possible_value().unwrap().get_name(),
+        args.roughness.map_or("none".into(), |r| r.to_string()),
+        args.glass_roughness
+            .map_or("none".into(), |r| r.to_string()),
+        palette,
+        args.mesh
+            .as_ref()
+            .map_or("none".into(), |path| path.display().to_string()),
+    )
+}
+
 fn main() {
-    let nx = 500;
-    let ny = 500;
-    let ns = 1000;
-    println!("P3\n{} {}\n255", nx, ny);
-    let (world, light_shape, cam) = cornell_box(nx as f32 / ny as f32);
-    let image = (0..ny)
-        .into_par_iter()
-        .rev()
-        .flat_map(|y| {
-            (0..nx)
-                .flat_map(|x| {
-                    let col: Vector3<f32> = (0..ns)
-                        .map(|_| {
-                            let mut rng = rand::thread_rng();
-                            let u = (x as f32 + rng.gen::<f32>()) / nx as f32;
-                            let v = (y as f32 + rng.gen::<f32>()) / ny as f32;
-                            let ray = cam.get_ray(u, v);
-                            color(&ray, &world, &light_shape, 0)
-                        })
-                        .sum();
-                    col.iter()
-                        .map(|c| (255.99 * (c / ns as f32).sqrt().max(0.0).min(1.0)) as u8)
-                        .collect::<Vec<u8>>()
-                })
-                .collect::<Vec<u8>>()
-        })
-        .collect::<Vec<u8>>();
-    for col in image.chunks(3) {
-        println!("{} {} {}", col[0], col[1], col[2]);
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: Args) -> io::Result<()> {
+    for path in args.output.iter().chain(args.sample_map.iter()) {
+        output::Format::from_path(path)?;
+    }
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("failed to build thread pool");
+    }
+    let nx = args.width;
+    let ny = args.height;
+    let max_samples = args.samples;
+    let min_samples = args.min_samples.min(max_samples);
+    let mesh = match &args.mesh {
+        Some(path) if path.extension().is_some_and(|e| e == "ply") => Some(ply::load(path, None)?),
+        Some(path) => Some(obj::load(path, None)?),
+        None => None,
+    };
+    let palette = if args.principled {
+        Palette::principled(args.roughness, args.glass_roughness)
+    } else if args.layered {
+        Palette::layered(args.roughness, args.glass_roughness)
+    } else {
+        Palette::classic(args.roughness, args.glass_roughness)
+    };
+    let (world, light_shape, cam) = cornell_box(nx as f32 / ny as f32, args.lamp, palette, mesh);
+    let mut film = Film::new(nx, ny);
+    let mut seed = args.seed;
+    let scene = scene_options(&args);
+    if let (Some(path), true) = (&args.checkpoint, args.resume) {
+        (film, seed) = checkpoint::load(path, &scene)?;
+        if (film.width(), film.height()) != (nx, ny) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "checkpoint '{}' is {}x{}, not {}x{}",
+                    path.display(),
+                    film.width(),
+                    film.height(),
+                    nx,
+                    ny
+                ),
+            ));
+        }
+    }
+    let mut renderer = Renderer::new(args.tile_size).progress(!args.quiet);
+    if let Some(path) = &args.checkpoint {
+        let interval = Duration::from_secs(args.checkpoint_interval);
+        let scene = &scene;
+        renderer = renderer.checkpoint(interval, move |film| {
+            if let Err(e) = checkpoint::save(path, film, seed, scene) {
+                eprintln!("\nfailed to write checkpoint '{}': {}", path.display(), e);
+            }
+        });
+    }
+    renderer.render(&mut film, |x, row, pixel| {
+        let y = ny - 1 - row;
+        let index = y as u64 * nx as u64 + x as u64;
+        while pixel.samples < max_samples {
+            if let Some(threshold) = args.threshold {
+                if pixel.samples >= min_samples && pixel.relative_error() < threshold {
+                    break;
+                }
+            }
+            let mut rng = Sampler::new(seed, index, pixel.samples as u64);
+            let u = (x as f32 + rng.gen::<f32>()) / nx as f32;
+            let v = (y as f32 + rng.gen::<f32>()) / ny as f32;
+            let ray = cam.get_ray(u, v, &mut rng);
+            pixel.add_sample(color(
+                &ray,
+                world.as_ref(),
+                light_shape.as_ref(),
+                0,
+                &mut rng,
+            ));
+        }
+    });
+    if let Some(path) = &args.checkpoint {
+        checkpoint::save(path, &film, seed, &scene)?;
+    }
+    if let Some(path) = &args.sample_map {
+        output::write_sample_map(path, &film, max_samples)?;
+    }
+    let tonemap = ToneMapper::new(args.tonemap, args.exposure, args.white);
+    match &args.output {
+        Some(path) => output::write_image(path, &film, &tonemap),
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            output::write_ppm(&mut out, &film, &tonemap)?;
+            out.flush()
+        }
     }
 }
@@ -1,13 +1,17 @@
 use crate::hittable::HitRecord;
-use crate::pdf::PDF;
+use crate::microfacet;
+use crate::microfacet::{RoughInterface, GGX};
+use crate::onb::ONB;
+use crate::pdf::{Lobe, MAX_LOBES, PDF};
 use crate::ray::Ray;
-use crate::texture::Texture;
+use crate::sampler::Sampler;
+use crate::texture::{ConstantTexture, Texture};
 use nalgebra::Vector3;
 use rand::Rng;
 use std::f32;
+use std::sync::Arc;
 
-fn random_in_unit_sphere() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+fn random_in_unit_sphere(rng: &mut Sampler) -> Vector3<f32> {
     let unit = Vector3::new(1.0, 1.0, 1.0);
     loop {
         let p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) - unit;
@@ -33,6 +37,16 @@ fn refract(v: &Vector3<f32>, n: &Vector3<f32>, ni_over_nt: f32) -> Option<Vector
     }
 }
 
+/// Directions towards the viewer and `scattered` in the shading frame of
+/// `hit`.
+fn shading_directions(ray: &Ray, hit: &HitRecord, scattered: &Ray) -> (Vector3<f32>, Vector3<f32>) {
+    let uvw = ONB::build_from_w(&hit.shading_normal);
+    (
+        uvw.to_local(&-ray.direction().normalize()),
+        uvw.to_local(&scattered.direction().normalize()),
+    )
+}
+
 fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
@@ -50,19 +64,63 @@ pub enum ScatterRecord<'a> {
 }
 
 pub trait Material: Sync {
-    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit: &HitRecord,
+        _rng: &mut Sampler,
+    ) -> Option<ScatterRecord<'_>> {
         None
     }
 
+    /// Density with which the pdf of `scatter` picks `scattered`.
     fn scattering_pdf(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> f32 {
         1.0
     }
 
+    /// The BSDF times the cosine towards `scattered` per color channel, which
+    /// multiplies the attenuation of the scatter record. The default of
+    /// `scattering_pdf` on every channel fits colorless materials that sample
+    /// in proportion to their BSDF. Colors belong here rather than in the
+    /// attenuation, which `MixMaterial` and `Coated` cannot blend.
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        Vector3::repeat(self.scattering_pdf(ray, hit, scattered))
+    }
+
+    /// The chance that `scatter` returns a `Scatter` record at `hit` and the
+    /// pdf of that record, or `None` if it never does. `scattering` covers
+    /// only this share of the light, the rest being specular.
+    fn scatter_pdf(&self, _ray: &Ray, _hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        None
+    }
+
     fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Vector3<f32> {
         Vector3::zeros()
     }
 }
 
+impl<M: Material + Send + ?Sized> Material for Arc<M> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        (**self).scatter(ray, hit, rng)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        (**self).scattering_pdf(ray, hit, scattered)
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        (**self).scattering(ray, hit, scattered)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        (**self).scatter_pdf(ray, hit)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+        (**self).emitted(ray, hit)
+    }
+}
+
 #[derive(Clone)]
 pub struct Lambertian<T: Texture> {
     albedo: T,
@@ -75,17 +133,33 @@ impl<T: Texture> Lambertian<T> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        hit: &HitRecord,
+        _rng: &mut Sampler,
+    ) -> Option<ScatterRecord<'_>> {
         Some(ScatterRecord::Scatter {
-            pdf: PDF::cosine(hit.normal),
-            attenuation: self.albedo.value(hit.u, hit.v, &hit.p),
+            pdf: PDF::cosine(hit.shading_normal),
+            attenuation: Vector3::repeat(1.0),
         })
     }
 
     fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
-        let cosine = hit.normal.dot(&scattered.direction().normalize()).max(0.0);
+        let cosine = hit
+            .shading_normal
+            .dot(&scattered.direction().normalize())
+            .max(0.0);
         cosine / f32::consts::PI
     }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        self.albedo.value(hit.u, hit.v, &hit.p) * self.scattering_pdf(ray, hit, scattered)
+    }
+
+    fn scatter_pdf(&self, _ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        Some((1.0, PDF::cosine(hit.shading_normal)))
+    }
 }
 
 #[derive(Clone)]
@@ -104,12 +178,12 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
-        let mut reflected = reflect(&ray.direction().normalize(), &hit.normal);
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        let mut reflected = reflect(&ray.direction().normalize(), &hit.shading_normal);
         if self.fuzz > 0.0 {
-            reflected += self.fuzz * random_in_unit_sphere()
+            reflected += self.fuzz * random_in_unit_sphere(rng)
         };
-        if reflected.dot(&hit.normal) > 0.0 {
+        if reflected.dot(&hit.shading_normal) > 0.0 {
             Some(ScatterRecord::Specular {
                 specular_ray: Ray::new(hit.p, reflected, ray.time()),
                 attenuation: self.albedo,
@@ -120,6 +194,88 @@ impl Material for Metal {
     }
 }
 
+/// Rough metal with a GGX microfacet distribution and the Fresnel
+/// reflectance of its complex index of refraction `eta + i k`, given per
+/// color channel.
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Vector3<f32>,
+    k: Vector3<f32>,
+    ggx: GGX,
+}
+
+impl Conductor {
+    /// A `roughness` of 0 gives a mirror.
+    pub fn new(eta: Vector3<f32>, k: Vector3<f32>, roughness: f32) -> Self {
+        Conductor {
+            eta,
+            k,
+            ggx: GGX::from_roughness(roughness),
+        }
+    }
+
+    /// One of `gold`, `silver`, `copper` or `aluminium`, with its index of
+    /// refraction at 650, 550 and 450 nm.
+    pub fn named(name: &str, roughness: f32) -> Option<Self> {
+        let (eta, k) = match name {
+            "gold" => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            "silver" => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            "copper" => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            "aluminium" => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            _ => return None,
+        };
+        Some(Conductor::new(eta.into(), k.into(), roughness))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        let cos_i = -ray.direction().normalize().dot(&hit.shading_normal);
+        if cos_i <= 0.0 {
+            return None;
+        }
+        if self.ggx.is_smooth() {
+            return Some(ScatterRecord::Specular {
+                specular_ray: Ray::new(
+                    hit.p,
+                    reflect(&ray.direction(), &hit.shading_normal),
+                    ray.time(),
+                ),
+                attenuation: microfacet::fresnel_conductor(cos_i, &self.eta, &self.k),
+            });
+        }
+        Some(ScatterRecord::Scatter {
+            pdf: PDF::lobe(hit.shading_normal, -ray.direction(), Lobe::GGX(self.ggx)),
+            attenuation: Vector3::repeat(1.0),
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        Lobe::GGX(self.ggx).value(&wi, &wo)
+    }
+
+    /// F D G / (4 cos_i).
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return Vector3::zeros();
+        }
+        let h = (wi + wo).normalize();
+        microfacet::fresnel_conductor(wi.dot(&h), &self.eta, &self.k)
+            * (self.ggx.d(&h) * self.ggx.g(&wi, &wo) / (4.0 * wi.z))
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        (!self.ggx.is_smooth() && ray.direction().dot(&hit.shading_normal) < 0.0).then(|| {
+            (
+                1.0,
+                PDF::lobe(hit.shading_normal, -ray.direction(), Lobe::GGX(self.ggx)),
+            )
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct Dielectric {
     ref_idx: f32,
@@ -132,26 +288,26 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
         let attenuation = Vector3::new(1.0, 1.0, 1.0);
+        let normal = hit.shading_normal;
         let (outward_normal, ni_over_nt, cosine) = if ray.direction().dot(&hit.normal) > 0.0 {
-            let cosine =
-                self.ref_idx * ray.direction().dot(&hit.normal) / ray.direction().magnitude();
-            (-hit.normal, self.ref_idx, cosine)
+            let cosine = self.ref_idx * ray.direction().dot(&normal) / ray.direction().magnitude();
+            (-normal, self.ref_idx, cosine)
         } else {
-            let cosine = -ray.direction().dot(&hit.normal) / ray.direction().magnitude();
-            (hit.normal, 1.0 / self.ref_idx, cosine)
+            let cosine = -ray.direction().dot(&normal) / ray.direction().magnitude();
+            (normal, 1.0 / self.ref_idx, cosine)
         };
         if let Some(refracted) = refract(&ray.direction(), &outward_normal, ni_over_nt) {
             let reflect_prob = schlick(cosine, self.ref_idx);
-            if rand::thread_rng().gen::<f32>() >= reflect_prob {
+            if rng.gen::<f32>() >= reflect_prob {
                 return Some(ScatterRecord::Specular {
                     specular_ray: Ray::new(hit.p, refracted, ray.time()),
                     attenuation,
                 });
             }
         }
-        let reflected = reflect(&ray.direction(), &hit.normal);
+        let reflected = reflect(&ray.direction(), &normal);
         Some(ScatterRecord::Specular {
             specular_ray: Ray::new(hit.p, reflected, ray.time()),
             attenuation,
@@ -159,6 +315,85 @@ impl Material for Dielectric {
     }
 }
 
+/// Frosted glass: a dielectric whose surface has GGX microfacets that both
+/// reflect and refract, with exact Fresnel reflectance.
+#[derive(Clone)]
+pub struct RoughDielectric {
+    interface: RoughInterface,
+}
+
+impl RoughDielectric {
+    /// A `roughness` of 0 gives clear glass.
+    pub fn new(ref_idx: f32, roughness: f32) -> Self {
+        RoughDielectric {
+            interface: RoughInterface {
+                ggx: GGX::from_roughness(roughness),
+                eta: ref_idx,
+            },
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        if !self.interface.ggx.is_smooth() {
+            return Some(ScatterRecord::Scatter {
+                pdf: PDF::lobe(
+                    hit.shading_normal,
+                    -ray.direction(),
+                    Lobe::Dielectric(self.interface),
+                ),
+                attenuation: Vector3::repeat(1.0),
+            });
+        }
+        let direction = ray.direction().normalize();
+        let cos_i = -direction.dot(&hit.shading_normal);
+        let eta = self.interface.eta;
+        let reflectance = microfacet::fresnel_dielectric(cos_i, eta);
+        let (normal, ratio) = if cos_i > 0.0 {
+            (hit.shading_normal, eta)
+        } else {
+            (-hit.shading_normal, 1.0 / eta)
+        };
+        let (direction, attenuation) = match refract(&direction, &normal, 1.0 / ratio) {
+            Some(refracted) if rng.gen::<f32>() >= reflectance => {
+                (refracted, Vector3::repeat(1.0 / (ratio * ratio)))
+            }
+            _ => (
+                reflect(&direction, &hit.shading_normal),
+                Vector3::repeat(1.0),
+            ),
+        };
+        Some(ScatterRecord::Specular {
+            specular_ray: Ray::new(hit.p, direction, ray.time()),
+            attenuation,
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        self.interface.pdf(&wi, &wo)
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        Vector3::repeat(self.interface.eval(&wi, &wo))
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        (!self.interface.ggx.is_smooth()).then(|| {
+            (
+                1.0,
+                PDF::lobe(
+                    hit.shading_normal,
+                    -ray.direction(),
+                    Lobe::Dielectric(self.interface),
+                ),
+            )
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct DiffuseLight<T: Texture> {
     emit: T,
@@ -179,3 +414,504 @@ impl<T: Texture> Material for DiffuseLight<T> {
         }
     }
 }
+
+type SharedTexture = Arc<dyn Texture + Send>;
+
+/// Roughness of the clear coat on `Principled`.
+const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+/// Disney-style uber material mixing a diffuse base with sheen, a GGX
+/// specular layer that turns into a conductor with `metallic`, a clear coat
+/// and rough glass for `transmission`. Every parameter is a texture; the
+/// scalar ones read its first channel.
+#[derive(Clone)]
+pub struct Principled {
+    base_color: SharedTexture,
+    metallic: SharedTexture,
+    roughness: SharedTexture,
+    specular: SharedTexture,
+    sheen: SharedTexture,
+    clearcoat: SharedTexture,
+    transmission: SharedTexture,
+    emission: SharedTexture,
+    ior: f32,
+}
+
+/// Parameters of a `Principled` material at one point.
+struct Surface {
+    base_color: Vector3<f32>,
+    metallic: f32,
+    specular: f32,
+    sheen: Vector3<f32>,
+    clearcoat: f32,
+    transmission: f32,
+    roughness: f32,
+    ggx: GGX,
+    interface: RoughInterface,
+}
+
+impl Principled {
+    /// A dielectric with a roughness of 0.5 and the usual 4% reflectance.
+    pub fn new(base_color: impl Texture + Send + 'static) -> Self {
+        let constant = |value| Arc::new(ConstantTexture::new(value, value, value));
+        Principled {
+            base_color: Arc::new(base_color),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            transmission: constant(0.0),
+            emission: constant(0.0),
+            ior: 1.5,
+        }
+    }
+
+    /// Blends from a dielectric at 0 to a conductor tinted by the base color
+    /// at 1.
+    pub fn metallic(mut self, metallic: impl Texture + Send + 'static) -> Self {
+        self.metallic = Arc::new(metallic);
+        self
+    }
+
+    /// Square root of the GGX alpha of the specular layer and the glass.
+    pub fn roughness(mut self, roughness: impl Texture + Send + 'static) -> Self {
+        self.roughness = Arc::new(roughness);
+        self
+    }
+
+    /// Normal reflectance of the dielectric in units of 8%.
+    pub fn specular(mut self, specular: impl Texture + Send + 'static) -> Self {
+        self.specular = Arc::new(specular);
+        self
+    }
+
+    /// Color of the retroreflection at grazing angles, as on cloth.
+    pub fn sheen(mut self, sheen: impl Texture + Send + 'static) -> Self {
+        self.sheen = Arc::new(sheen);
+        self
+    }
+
+    /// Strength of a glossy uncolored coat on top.
+    pub fn clearcoat(mut self, clearcoat: impl Texture + Send + 'static) -> Self {
+        self.clearcoat = Arc::new(clearcoat);
+        self
+    }
+
+    /// Fraction of the dielectric that is glass instead of diffuse.
+    pub fn transmission(mut self, transmission: impl Texture + Send + 'static) -> Self {
+        self.transmission = Arc::new(transmission);
+        self
+    }
+
+    pub fn emission(mut self, emission: impl Texture + Send + 'static) -> Self {
+        self.emission = Arc::new(emission);
+        self
+    }
+
+    /// Index of refraction of the glass.
+    pub fn ior(mut self, ior: f32) -> Self {
+        self.ior = ior;
+        self
+    }
+
+    fn surface(&self, hit: &HitRecord) -> Surface {
+        let value = |texture: &SharedTexture| texture.value(hit.u, hit.v, &hit.p);
+        let scalar = |texture: &SharedTexture| value(texture).x.clamp(0.0, 1.0);
+        let roughness = scalar(&self.roughness).max(0.04);
+        let ggx = GGX::from_roughness(roughness);
+        Surface {
+            base_color: value(&self.base_color),
+            metallic: scalar(&self.metallic),
+            specular: scalar(&self.specular),
+            sheen: value(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            transmission: scalar(&self.transmission),
+            roughness,
+            ggx,
+            interface: RoughInterface { ggx, eta: self.ior },
+        }
+    }
+
+    /// Lobes to sample, picked in proportion to their weight in the BSDF
+    /// and the reflectance of the layers at the angle of `ray`. Only the
+    /// glass scatters light from below the surface.
+    fn pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<PDF<'_>> {
+        let surface = self.surface(hit);
+        let dielectric = 1.0 - surface.metallic;
+        let cos_i = -ray.direction().normalize().dot(&hit.shading_normal);
+        let mut lobes = [(0.0, Lobe::Cosine); MAX_LOBES];
+        lobes[0].0 = dielectric * (1.0 - surface.transmission);
+        lobes[1] = (
+            (1.0 - dielectric * surface.transmission) * schlick_color(cos_i, surface.r0()).mean(),
+            Lobe::GGX(surface.ggx),
+        );
+        lobes[2] = (
+            0.25 * surface.clearcoat * schlick(cos_i, 1.5),
+            Lobe::GGX(GGX::from_roughness(CLEARCOAT_ROUGHNESS)),
+        );
+        lobes[3] = (
+            dielectric * surface.transmission,
+            Lobe::Dielectric(surface.interface),
+        );
+        if cos_i <= 0.0 {
+            for lobe in &mut lobes[..3] {
+                lobe.0 = 0.0;
+            }
+        }
+        let total: f32 = lobes.iter().map(|(weight, _)| weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        for lobe in &mut lobes {
+            lobe.0 /= total;
+        }
+        Some(PDF::lobes(hit.shading_normal, -ray.direction(), lobes))
+    }
+}
+
+impl Surface {
+    /// Normal reflectance of the specular layer.
+    fn r0(&self) -> Vector3<f32> {
+        Vector3::repeat(0.08 * self.specular).lerp(&self.base_color, self.metallic)
+    }
+}
+
+fn schlick_color(cosine: f32, r0: Vector3<f32>) -> Vector3<f32> {
+    r0 + (Vector3::repeat(1.0) - r0) * (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, _rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        Some(ScatterRecord::Scatter {
+            pdf: self.pdf(ray, hit)?,
+            attenuation: Vector3::repeat(1.0),
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        self.pdf(ray, hit)
+            .map_or(0.0, |pdf| pdf.value(scattered.direction()))
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        let surface = self.surface(hit);
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        let dielectric = 1.0 - surface.metallic;
+        if wi.z <= 0.0 {
+            // Light inside can only have entered through the glass.
+            return if dielectric * surface.transmission > 0.0 {
+                Vector3::repeat(surface.interface.eval(&wi, &wo))
+            } else {
+                Vector3::zeros()
+            };
+        }
+        let glass = surface.interface.eval(&wi, &wo);
+        let glass = if wo.z < 0.0 {
+            surface.base_color * glass
+        } else {
+            Vector3::repeat(glass)
+        };
+        let mut value = dielectric * surface.transmission * glass;
+        if wo.z <= 0.0 {
+            return value;
+        }
+        let h = (wi + wo).normalize();
+        let cos_d = wo.dot(&h);
+        // Burley's diffuse, which darkens at grazing angles on smooth
+        // surfaces and brightens on rough ones, plus sheen.
+        let fd90 = 0.5 + 2.0 * surface.roughness * cos_d * cos_d;
+        let fresnel = |cos: f32| 1.0 + (fd90 - 1.0) * (1.0 - cos).powi(5);
+        let diffuse = surface.base_color * fresnel(wi.z) * fresnel(wo.z) / f32::consts::PI
+            + surface.sheen * (1.0 - cos_d).powi(5);
+        value += dielectric * (1.0 - surface.transmission) * diffuse * wo.z;
+        let specular =
+            schlick_color(wi.dot(&h), surface.r0()) * surface.ggx.d(&h) * surface.ggx.g(&wi, &wo)
+                / (4.0 * wi.z);
+        value += (1.0 - dielectric * surface.transmission) * specular;
+        let coat = GGX::from_roughness(CLEARCOAT_ROUGHNESS);
+        let coat = schlick(wi.dot(&h), 1.5) * coat.d(&h) * coat.g(&wi, &wo) / (4.0 * wi.z);
+        value + Vector3::repeat(0.25 * surface.clearcoat * coat)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        self.pdf(ray, hit).map(|pdf| (1.0, pdf))
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+        if hit.normal.dot(&ray.direction()) < 0.0 {
+            self.emission.value(hit.u, hit.v, &hit.p)
+        } else {
+            Vector3::zeros()
+        }
+    }
+}
+
+/// Picks one of `parts` with the chance given by its share, from `pick`
+/// in [0, 1). Rounding falls back on the last part with a share.
+fn pick_part<T: Copy>(parts: &[(f32, T)], mut pick: f32) -> Option<T> {
+    parts
+        .iter()
+        .find(|(share, _)| {
+            pick -= share;
+            pick < 0.0
+        })
+        .or_else(|| parts.iter().rev().find(|(share, _)| *share > 0.0))
+        .map(|(_, part)| *part)
+}
+
+/// Two parts of a material that scatter with a pdf with the given chances,
+/// as one pdf with the chance of either.
+fn blend_pdfs<'a>(parts: [Option<(f32, PDF<'a>)>; 2]) -> Option<(f32, PDF<'a>)> {
+    match parts {
+        [Some((a, p)), Some((b, q))] if a > 0.0 && b > 0.0 => {
+            Some((a + b, PDF::blend(p, q, b / (a + b))))
+        }
+        [Some((a, p)), _] if a > 0.0 => Some((a, p)),
+        [_, Some((b, q))] if b > 0.0 => Some((b, q)),
+        _ => None,
+    }
+}
+
+/// Blend of two materials, `first` where the first channel of `mask` is 0
+/// and `second` where it is 1, such as rust over metal. One of them is
+/// picked with its share, but where it would scatter with a pdf the blend
+/// of the pdfs of both is sampled instead, which `scattering_pdf` matches.
+#[derive(Clone)]
+pub struct MixMaterial<A: Material, B: Material, T: Texture> {
+    first: A,
+    second: B,
+    mask: T,
+}
+
+impl<A: Material, B: Material, T: Texture> MixMaterial<A, B, T> {
+    pub fn new(first: A, second: B, mask: T) -> Self {
+        MixMaterial {
+            first,
+            second,
+            mask,
+        }
+    }
+
+    /// Both materials with their shares at `hit`.
+    fn parts(&self, hit: &HitRecord) -> [(f32, &dyn Material); 2] {
+        let weight = self.mask.value(hit.u, hit.v, &hit.p).x.clamp(0.0, 1.0);
+        [(1.0 - weight, &self.first), (weight, &self.second)]
+    }
+
+    /// Both materials with their shares of the light scattered with a pdf,
+    /// which add up to 1 unless neither has one.
+    fn pdf_parts(&self, ray: &Ray, hit: &HitRecord) -> [(f32, &dyn Material); 2] {
+        let mut parts = self.parts(hit).map(|(share, material)| {
+            let chance = material
+                .scatter_pdf(ray, hit)
+                .map_or(0.0, |(chance, _)| chance);
+            (share * chance, material)
+        });
+        let total: f32 = parts.iter().map(|(share, _)| share).sum();
+        if total > 0.0 {
+            for part in &mut parts {
+                part.0 /= total;
+            }
+        }
+        parts
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Material for MixMaterial<A, B, T> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        let material = pick_part(&self.parts(hit), rng.gen::<f32>())?;
+        match material.scatter(ray, hit, rng)? {
+            ScatterRecord::Scatter { .. } => Some(ScatterRecord::Scatter {
+                pdf: self.scatter_pdf(ray, hit)?.1,
+                attenuation: Vector3::repeat(1.0),
+            }),
+            specular => Some(specular),
+        }
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        self.pdf_parts(ray, hit)
+            .iter()
+            .filter(|(share, _)| *share > 0.0)
+            .map(|(share, material)| share * material.scattering_pdf(ray, hit, scattered))
+            .sum()
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        self.pdf_parts(ray, hit)
+            .iter()
+            .filter(|(share, _)| *share > 0.0)
+            .map(|(share, material)| *share * material.scattering(ray, hit, scattered))
+            .sum()
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        blend_pdfs(self.parts(hit).map(|(share, material)| {
+            material
+                .scatter_pdf(ray, hit)
+                .map(|(chance, pdf)| (share * chance, pdf))
+        }))
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+        self.parts(hit)
+            .iter()
+            .map(|(share, material)| *share * material.emitted(ray, hit))
+            .sum()
+    }
+}
+
+/// A clear dielectric coat over `base`, like varnish or the lacquer of car
+/// paint. The coat reflects its Fresnel reflectance off GGX microfacets and
+/// the base gets the rest, losing what the coat reflects back in on the way
+/// out. Light from below sees only the base.
+#[derive(Clone)]
+pub struct Coated<M: Material> {
+    base: M,
+    ior: f32,
+    ggx: GGX,
+}
+
+impl<M: Material> Coated<M> {
+    /// A `roughness` of 0 gives a smooth coat.
+    pub fn new(base: M, ior: f32, roughness: f32) -> Self {
+        Coated {
+            base,
+            ior,
+            ggx: GGX::from_roughness(roughness),
+        }
+    }
+
+    /// Fraction of the light that the coat reflects at the cosine `cos`.
+    fn reflectance(&self, cos: f32) -> f32 {
+        microfacet::fresnel_dielectric(cos.abs(), self.ior)
+    }
+
+    /// Chances that the coat and the base scatter with a pdf for light
+    /// arriving at the cosine `cos_i` above the surface.
+    fn pdf_shares(&self, ray: &Ray, hit: &HitRecord, cos_i: f32) -> (f32, f32) {
+        let coat = self.reflectance(cos_i);
+        let base = self
+            .base
+            .scatter_pdf(ray, hit)
+            .map_or(0.0, |(chance, _)| chance);
+        (
+            if self.ggx.is_smooth() { 0.0 } else { coat },
+            (1.0 - coat) * base,
+        )
+    }
+}
+
+impl<M: Material> Material for Coated<M> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Sampler) -> Option<ScatterRecord<'_>> {
+        let cos_i = -ray.direction().normalize().dot(&hit.shading_normal);
+        if cos_i <= 0.0 {
+            return self.base.scatter(ray, hit, rng);
+        }
+        let scatter = || {
+            Some(ScatterRecord::Scatter {
+                pdf: self.scatter_pdf(ray, hit)?.1,
+                attenuation: Vector3::repeat(1.0),
+            })
+        };
+        // Picking the coat by its reflectance and the base by the rest
+        // cancels the way in.
+        if rng.gen::<f32>() < self.reflectance(cos_i) {
+            if !self.ggx.is_smooth() {
+                return scatter();
+            }
+            return Some(ScatterRecord::Specular {
+                specular_ray: Ray::new(
+                    hit.p,
+                    reflect(&ray.direction(), &hit.shading_normal),
+                    ray.time(),
+                ),
+                attenuation: Vector3::repeat(1.0),
+            });
+        }
+        match self.base.scatter(ray, hit, rng)? {
+            ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            } => {
+                let cos_o = specular_ray
+                    .direction()
+                    .normalize()
+                    .dot(&hit.shading_normal);
+                Some(ScatterRecord::Specular {
+                    specular_ray,
+                    attenuation: attenuation * (1.0 - self.reflectance(cos_o)),
+                })
+            }
+            ScatterRecord::Scatter { .. } => scatter(),
+        }
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        if wi.z <= 0.0 {
+            return self.base.scattering_pdf(ray, hit, scattered);
+        }
+        let (coat, base) = self.pdf_shares(ray, hit, wi.z);
+        if coat + base <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = 0.0;
+        if coat > 0.0 {
+            pdf += coat * Lobe::GGX(self.ggx).value(&wi, &wo);
+        }
+        if base > 0.0 {
+            pdf += base * self.base.scattering_pdf(ray, hit, scattered);
+        }
+        pdf / (coat + base)
+    }
+
+    /// The coat's F D G / (4 cos_i) plus the base through the coat, over
+    /// the chance of scattering with a pdf.
+    fn scattering(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vector3<f32> {
+        let (wi, wo) = shading_directions(ray, hit, scattered);
+        if wi.z <= 0.0 {
+            return self.base.scattering(ray, hit, scattered);
+        }
+        let (coat, base) = self.pdf_shares(ray, hit, wi.z);
+        if coat + base <= 0.0 {
+            return Vector3::zeros();
+        }
+        let mut value = Vector3::zeros();
+        if coat > 0.0 && wo.z > 0.0 {
+            let h = (wi + wo).normalize();
+            value += Vector3::repeat(
+                self.reflectance(wi.dot(&h)) * self.ggx.d(&h) * self.ggx.g(&wi, &wo) / (4.0 * wi.z),
+            );
+        }
+        if base > 0.0 {
+            let out = 1.0 - self.reflectance(wo.z);
+            value += base * out * self.base.scattering(ray, hit, scattered);
+        }
+        value / (coat + base)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord) -> Option<(f32, PDF<'_>)> {
+        let cos_i = -ray.direction().normalize().dot(&hit.shading_normal);
+        if cos_i <= 0.0 {
+            return self.base.scatter_pdf(ray, hit);
+        }
+        let coat = self.reflectance(cos_i);
+        blend_pdfs([
+            (!self.ggx.is_smooth()).then(|| {
+                (
+                    coat,
+                    PDF::lobe(hit.shading_normal, -ray.direction(), Lobe::GGX(self.ggx)),
+                )
+            }),
+            self.base
+                .scatter_pdf(ray, hit)
+                .map(|(chance, pdf)| ((1.0 - coat) * chance, pdf)),
+        ])
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+        self.base.emitted(ray, hit)
+    }
+}
@@ -0,0 +1,236 @@
+use crate::sampler::Sampler;
+use nalgebra::{Complex, Vector3};
+use rand::Rng;
+use std::f32;
+
+// Directions here are in a shading frame with the surface normal along +z.
+
+/// Below this alpha a surface is treated as perfectly smooth.
+pub const MIN_ALPHA: f32 = 1e-3;
+
+/// Isotropic GGX (Trowbridge-Reitz) distribution of microfacet normals.
+#[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct GGX {
+    alpha: f32,
+}
+
+impl GGX {
+    /// `roughness` is the square root of alpha, which makes highlights
+    /// change more evenly over its range.
+    pub fn from_roughness(roughness: f32) -> Self {
+        GGX {
+            alpha: roughness.clamp(0.0, 1.0).powi(2),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < MIN_ALPHA
+    }
+
+    /// Density of microfacet normals `h` per unit projected area.
+    pub fn d(&self, h: &Vector3<f32>) -> f32 {
+        let cos2 = h.z * h.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let tan2 = (1.0 - cos2) / cos2;
+        a2 / (f32::consts::PI * cos2 * cos2 * (a2 + tan2).powi(2))
+    }
+
+    fn lambda(&self, w: &Vector3<f32>) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt()) / 2.0
+    }
+
+    /// Fraction of the microfacets facing `w` that are visible from it.
+    pub fn g1(&self, w: &Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking and shadowing for `wi` and `wo`.
+    pub fn g(&self, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(wi) + self.lambda(wo))
+    }
+
+    /// Samples a microfacet normal visible from `wi`, which must be above
+    /// the surface (Heitz 2018).
+    pub fn sample_visible(&self, wi: &Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        // Stretch the view to the configuration of a hemisphere.
+        let vh = Vector3::new(self.alpha * wi.x, self.alpha * wi.y, wi.z).normalize();
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vector3::new(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vector3::x()
+        };
+        let t2 = vh.cross(&t1);
+        // Uniform point on the projected disk, squeezed onto the visible half.
+        let r = rng.gen::<f32>().sqrt();
+        let phi = 2.0 * f32::consts::PI * rng.gen::<f32>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vector3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).normalize()
+    }
+
+    /// Density of `sample_visible` returning `h` when seen from `wi`.
+    pub fn pdf_visible(&self, wi: &Vector3<f32>, h: &Vector3<f32>) -> f32 {
+        self.g1(wi) * wi.dot(h).max(0.0) * self.d(h) / wi.z
+    }
+}
+
+/// Mirror image of `wi` about `h`.
+pub fn reflect(wi: &Vector3<f32>, h: &Vector3<f32>) -> Vector3<f32> {
+    2.0 * wi.dot(h) * h - wi
+}
+
+/// Unpolarized reflectance of a surface with the complex index of
+/// refraction `eta + i k`, relative to the outside, at the cosine `cos_i`.
+fn fresnel_complex(cos_i: f32, eta: Complex<f32>) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_i = 1.0 - cos_i * cos_i;
+    let sin2_t = sin2_i / (eta * eta);
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel.norm_sqr() + r_perpendicular.norm_sqr()) / 2.0
+}
+
+/// Fresnel reflectance of a conductor per color channel.
+pub fn fresnel_conductor(cos_i: f32, eta: &Vector3<f32>, k: &Vector3<f32>) -> Vector3<f32> {
+    Vector3::from_fn(|i, _| fresnel_complex(cos_i, Complex::new(eta[i], k[i])))
+}
+
+/// Reflectance of a dielectric with relative index of refraction `eta` at
+/// the cosine `cos_i`, which is negative on the inside.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel.powi(2) + r_perpendicular.powi(2)) / 2.0
+}
+
+/// Refracts `wi` through a surface with normal `h` into a medium with
+/// relative index `eta` on the other side of `h`, or `None` for total
+/// internal reflection.
+fn refract(wi: &Vector3<f32>, h: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_i = wi.dot(h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wi / eta + (cos_i / eta - cos_t) * h)
+}
+
+/// Rough boundary of a dielectric with index `eta` relative to the outside,
+/// on the side of +z (Walter et al. 2007). Light passing through is scaled
+/// by the squared ratio of the indices, as radiance is.
+#[derive(Clone, Copy)]
+pub struct RoughInterface {
+    pub ggx: GGX,
+    pub eta: f32,
+}
+
+impl RoughInterface {
+    /// Ratio of the indices on the far and near side seen from `wi`.
+    fn eta_from(&self, wi: &Vector3<f32>) -> f32 {
+        if wi.z > 0.0 {
+            self.eta
+        } else {
+            1.0 / self.eta
+        }
+    }
+
+    /// Microfacet normal, facing +z, that takes `wi` to `wo`, and the index
+    /// ratio for transmission or 1 for reflection. `None` if the normal
+    /// faces away from either direction.
+    fn half_vector(&self, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Option<(Vector3<f32>, f32)> {
+        if wi.z == 0.0 || wo.z == 0.0 {
+            return None;
+        }
+        let eta = if wi.z * wo.z > 0.0 {
+            1.0
+        } else {
+            self.eta_from(wi)
+        };
+        let h = wo * eta + wi;
+        if h.norm_squared() == 0.0 {
+            return None;
+        }
+        let h = if h.z < 0.0 { -h } else { h }.normalize();
+        if h.dot(wi) * wi.z < 0.0 || h.dot(wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some((h, eta))
+    }
+
+    /// Density of visible normals seen from `wi`, which may lie below.
+    fn pdf_visible(&self, wi: &Vector3<f32>, h: &Vector3<f32>) -> f32 {
+        self.ggx.g1(wi) * wi.dot(h).abs() * self.ggx.d(h) / wi.z.abs()
+    }
+
+    /// The BSDF times |cos| of `wo`.
+    pub fn eval(&self, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        let Some((h, eta)) = self.half_vector(wi, wo) else {
+            return 0.0;
+        };
+        let f = fresnel_dielectric(wi.dot(&h), self.eta);
+        let dg = self.ggx.d(&h) * self.ggx.g(wi, wo);
+        if eta == 1.0 {
+            f * dg / (4.0 * wi.z.abs())
+        } else {
+            let denominator = (wo.dot(&h) + wi.dot(&h) / eta).powi(2) * wi.z.abs();
+            (1.0 - f) * dg * (wo.dot(&h) * wi.dot(&h) / denominator).abs() / (eta * eta)
+        }
+    }
+
+    /// Density of `sample` returning `wo`.
+    pub fn pdf(&self, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        let Some((h, eta)) = self.half_vector(wi, wo) else {
+            return 0.0;
+        };
+        let f = fresnel_dielectric(wi.dot(&h), self.eta);
+        if eta == 1.0 {
+            self.pdf_visible(wi, &h) / (4.0 * wi.dot(&h).abs()) * f
+        } else {
+            let denominator = (wo.dot(&h) + wi.dot(&h) / eta).powi(2);
+            self.pdf_visible(wi, &h) * wo.dot(&h).abs() / denominator * (1.0 - f)
+        }
+    }
+
+    /// Reflects or refracts `wi` off a visible microfacet, chosen by its
+    /// Fresnel reflectance. Returns the direction and its weight, the BSDF
+    /// times |cos| over the density, or `None` if the path ends.
+    pub fn sample(&self, wi: &Vector3<f32>, rng: &mut Sampler) -> Option<(Vector3<f32>, f32)> {
+        // Normals are sampled as seen from above and face +z.
+        let side = wi.z.signum();
+        let h = self.ggx.sample_visible(&(wi * side), rng);
+        let f = fresnel_dielectric(wi.dot(&h), self.eta);
+        let weight = |wo: &Vector3<f32>| self.ggx.g(wi, wo) / self.ggx.g1(wi);
+        if rng.gen::<f32>() < f {
+            let wo = reflect(wi, &h);
+            (wo.z * wi.z > 0.0).then(|| (wo, weight(&wo)))
+        } else {
+            let eta = self.eta_from(wi);
+            let wo = refract(wi, &(h * side), eta)?;
+            (wo.z * wi.z < 0.0).then(|| (wo, weight(&wo) / (eta * eta)))
+        }
+    }
+}
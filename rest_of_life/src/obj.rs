@@ -0,0 +1,239 @@
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::{ConstantTexture, ImageTexture};
+use crate::triangle::{Face, Mesh, MeshData};
+use nalgebra::{Vector2, Vector3};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+type SharedMaterial = Arc<dyn Material + Send>;
+
+fn error(path: &Path, line: usize, message: impl AsRef<str>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}:{}: {}", path.display(), line, message.as_ref()),
+    )
+}
+
+fn numbers<const N: usize>(
+    fields: SplitWhitespace,
+    path: &Path,
+    line: usize,
+) -> io::Result<[f32; N]> {
+    let mut numbers = [0.0; N];
+    let mut count = 0;
+    for field in fields {
+        if count < N {
+            numbers[count] = field
+                .parse()
+                .map_err(|_| error(path, line, format!("invalid number '{}'", field)))?;
+        }
+        count += 1;
+    }
+    if count < N {
+        return Err(error(path, line, format!("expected {} numbers", N)));
+    }
+    Ok(numbers)
+}
+
+#[derive(Default)]
+struct MtlMaterial {
+    diffuse: Vector3<f32>,
+    diffuse_map: Option<String>,
+    specular: Vector3<f32>,
+    emission: Vector3<f32>,
+    shininess: f32,
+    ior: f32,
+    opacity: f32,
+    illum: u32,
+}
+
+impl MtlMaterial {
+    /// Picks the closest material the renderer has: emissive surfaces become
+    /// lights, transparent or refracting ones (`d` < 1 or `illum` 4, 6, 7 or 9)
+    /// glass, surfaces that reflect more specularly than diffusely metal with a
+    /// fuzz derived from `Ns`, and everything else Lambertian.
+    fn to_material(&self, dir: &Path) -> Result<SharedMaterial, String> {
+        let max = |c: &Vector3<f32>| c.max();
+        if max(&self.emission) > 0.0 {
+            let e = self.emission;
+            return Ok(Arc::new(DiffuseLight::new(ConstantTexture::new(
+                e.x, e.y, e.z,
+            ))));
+        }
+        if self.opacity < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            return Ok(Arc::new(Dielectric::new(self.ior)));
+        }
+        if max(&self.specular) > max(&self.diffuse) && self.diffuse_map.is_none() {
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            return Ok(Arc::new(Metal::new(self.specular, fuzz)));
+        }
+        match &self.diffuse_map {
+            Some(file) => {
+                let path = dir.join(file);
+                let image = image::open(&path)
+                    .map_err(|e| format!("cannot load image '{}': {}", path.display(), e))?
+                    .to_rgb8();
+                let (nx, ny) = image.dimensions();
+                Ok(Arc::new(Lambertian::new(ImageTexture::new(
+                    image.into_raw(),
+                    nx,
+                    ny,
+                ))))
+            }
+            None => {
+                let d = self.diffuse;
+                Ok(Arc::new(Lambertian::new(ConstantTexture::new(
+                    d.x, d.y, d.z,
+                ))))
+            }
+        }
+    }
+}
+
+fn load_mtl(path: &Path, materials: &mut HashMap<String, SharedMaterial>) -> io::Result<()> {
+    let source = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut current: Option<(String, usize, MtlMaterial)> = None;
+    let mut finish = |current: Option<(String, usize, MtlMaterial)>| -> io::Result<()> {
+        if let Some((name, line, mtl)) = current {
+            let material = mtl.to_material(dir).map_err(|e| error(path, line, e))?;
+            materials.insert(name, material);
+        }
+        Ok(())
+    };
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let mut fields = text.split_whitespace();
+        let Some(keyword) = fields.next() else {
+            continue;
+        };
+        if keyword.starts_with('#') {
+            continue;
+        }
+        if keyword == "newmtl" {
+            finish(current.take())?;
+            let name = fields.collect::<Vec<_>>().join(" ");
+            let mtl = MtlMaterial {
+                diffuse: Vector3::repeat(0.8),
+                ior: 1.5,
+                opacity: 1.0,
+                ..Default::default()
+            };
+            current = Some((name, line, mtl));
+            continue;
+        }
+        let Some((_, _, mtl)) = &mut current else {
+            return Err(error(path, line, format!("'{}' before 'newmtl'", keyword)));
+        };
+        match keyword {
+            "Kd" => mtl.diffuse = numbers::<3>(fields, path, line)?.into(),
+            "Ks" => mtl.specular = numbers::<3>(fields, path, line)?.into(),
+            "Ke" => mtl.emission = numbers::<3>(fields, path, line)?.into(),
+            "Ns" => mtl.shininess = numbers::<1>(fields, path, line)?[0],
+            "Ni" => mtl.ior = numbers::<1>(fields, path, line)?[0],
+            "d" => mtl.opacity = numbers::<1>(fields, path, line)?[0],
+            "Tr" => mtl.opacity = 1.0 - numbers::<1>(fields, path, line)?[0],
+            "illum" => mtl.illum = numbers::<1>(fields, path, line)?[0] as u32,
+            // Options such as -s or -o are not supported, the file name comes last.
+            "map_Kd" => match fields.last() {
+                Some(file) => mtl.diffuse_map = Some(file.to_string()),
+                None => return Err(error(path, line, "'map_Kd' needs a file name")),
+            },
+            _ => {}
+        }
+    }
+    finish(current)
+}
+
+/// Resolves a 1-based or negative (relative to the end) OBJ index.
+fn index(field: &str, len: usize, path: &Path, line: usize) -> io::Result<usize> {
+    let i: i64 = field
+        .parse()
+        .map_err(|_| error(path, line, format!("invalid index '{}'", field)))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(error(path, line, format!("index {} out of range", i)));
+    }
+    Ok(resolved as usize)
+}
+
+/// Corner attributes of a triangle, if every corner has one.
+fn all(corners: [Option<usize>; 3]) -> Option<[usize; 3]> {
+    Some([corners[0]?, corners[1]?, corners[2]?])
+}
+
+/// Loads a Wavefront OBJ file and the MTL libraries it references. Polygons
+/// are split into triangle fans. Faces use `material` if given, otherwise the
+/// MTL material named by `usemtl`, and a grey Lambertian if there is none.
+pub fn load(path: &Path, material: Option<SharedMaterial>) -> io::Result<Mesh> {
+    let source = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let default: SharedMaterial = Arc::new(Lambertian::new(ConstantTexture::new(0.8, 0.8, 0.8)));
+    let mut materials = HashMap::new();
+    let mut current = material.clone().unwrap_or_else(|| default.clone());
+    let mut data = MeshData::default();
+    let mut faces = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let mut fields = text.split_whitespace();
+        match fields.next() {
+            Some("v") => data
+                .positions
+                .push(numbers::<3>(fields, path, line)?.into()),
+            Some("vn") => data.normals.push(numbers::<3>(fields, path, line)?.into()),
+            Some("vt") => {
+                let [u, v] = numbers::<2>(fields, path, line)?;
+                data.uvs.push(Vector2::new(u, v));
+            }
+            Some("f") => {
+                let mut corners = Vec::new();
+                for corner in fields {
+                    let mut parts = corner.split('/');
+                    let p = index(parts.next().unwrap(), data.positions.len(), path, line)?;
+                    let uv = match parts.next() {
+                        Some("") | None => None,
+                        Some(t) => Some(index(t, data.uvs.len(), path, line)?),
+                    };
+                    let n = match parts.next() {
+                        Some("") | None => None,
+                        Some(n) => Some(index(n, data.normals.len(), path, line)?),
+                    };
+                    corners.push((p, uv, n));
+                }
+                if corners.len() < 3 {
+                    return Err(error(path, line, "a face needs at least 3 vertices"));
+                }
+                for k in 1..corners.len() - 1 {
+                    let c = [corners[0], corners[k], corners[k + 1]];
+                    let face = Face {
+                        positions: c.map(|c| c.0),
+                        uvs: all(c.map(|c| c.1)),
+                        normals: all(c.map(|c| c.2)),
+                    };
+                    faces.push((face, current.clone()));
+                }
+            }
+            Some("mtllib") => {
+                for file in fields {
+                    load_mtl(&dir.join(file), &mut materials)?;
+                }
+            }
+            Some("usemtl") if material.is_none() => {
+                let name = fields.collect::<Vec<_>>().join(" ");
+                current = match materials.get(&name) {
+                    Some(m) => m.clone(),
+                    None => return Err(error(path, line, format!("unknown material '{}'", name))),
+                };
+            }
+            _ => {}
+        }
+    }
+    if faces.is_empty() {
+        return Err(error(path, source.lines().count(), "the file has no faces"));
+    }
+    Ok(Mesh::new(data, faces))
+}
@@ -1,5 +1,6 @@
 use nalgebra::Vector3;
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct ONB {
     axis: [Vector3<f32>; 3],
 }
@@ -30,4 +31,10 @@ impl ONB {
     pub fn local(&self, a: &Vector3<f32>) -> Vector3<f32> {
         a.x * self.u() + a.y * self.v() + a.z * self.w()
     }
+
+    /// Coordinates of the world space vector `a` in this basis, the inverse
+    /// of `local`.
+    pub fn to_local(&self, a: &Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(a.dot(&self.u()), a.dot(&self.v()), a.dot(&self.w()))
+    }
 }
@@ -0,0 +1,139 @@
+use crate::film::Film;
+use crate::tonemap::ToneMapper;
+use image::{ImageBuffer, ImageFormat, Rgb};
+use nalgebra::Vector3;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Image file formats, picked from the output file extension.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 8-bit PNG.
+    Png,
+    /// Binary 8-bit PPM (P6).
+    Ppm,
+    /// Portable float map, unclamped linear radiance.
+    Pfm,
+    /// OpenEXR, unclamped linear radiance.
+    Exr,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => Ok(Format::Png),
+            Some("ppm") => Ok(Format::Ppm),
+            Some("pfm") => Ok(Format::Pfm),
+            Some("exr") => Ok(Format::Exr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported output format '{}', expected .png, .ppm, .pfm or .exr",
+                    path.display()
+                ),
+            )),
+        }
+    }
+}
+
+fn to_rgb8(pixels: &[Vector3<f32>], tonemap: &ToneMapper) -> Vec<u8> {
+    pixels.iter().flat_map(|p| tonemap.to_srgb8(*p)).collect()
+}
+
+fn to_rgb32f(pixels: &[Vector3<f32>]) -> Vec<f32> {
+    pixels.iter().flat_map(|p| p.iter().copied()).collect()
+}
+
+fn image_error(e: image::ImageError) -> io::Error {
+    io::Error::other(e)
+}
+
+fn write_ppm_bytes(out: &mut impl Write, width: u32, height: u32, rgb8: &[u8]) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    out.write_all(rgb8)
+}
+
+fn write_pfm_floats(
+    out: &mut impl Write,
+    width: u32,
+    height: u32,
+    rgb32f: &[f32],
+) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in rgb32f.chunks(3 * width as usize).rev() {
+        for c in row {
+            out.write_all(&c.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_rgb(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgb8: impl FnOnce() -> Vec<u8>,
+    rgb32f: impl FnOnce() -> Vec<f32>,
+) -> io::Result<()> {
+    match Format::from_path(path)? {
+        Format::Png => ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, rgb8())
+            .expect("image size mismatch")
+            .save_with_format(path, ImageFormat::Png)
+            .map_err(image_error),
+        Format::Exr => ImageBuffer::<Rgb<f32>, _>::from_raw(width, height, rgb32f())
+            .expect("image size mismatch")
+            .save_with_format(path, ImageFormat::OpenExr)
+            .map_err(image_error),
+        Format::Ppm => {
+            let mut out = BufWriter::new(File::create(path)?);
+            write_ppm_bytes(&mut out, width, height, &rgb8())?;
+            out.flush()
+        }
+        Format::Pfm => {
+            let mut out = BufWriter::new(File::create(path)?);
+            write_pfm_floats(&mut out, width, height, &rgb32f())?;
+            out.flush()
+        }
+    }
+}
+
+/// Writes a tone mapped binary PPM (P6).
+pub fn write_ppm(out: &mut impl Write, film: &Film, tonemap: &ToneMapper) -> io::Result<()> {
+    let rgb8 = to_rgb8(&film.pixels(), tonemap);
+    write_ppm_bytes(out, film.width(), film.height(), &rgb8)
+}
+
+/// Writes `film` to `path` in the format given by its extension. 8-bit formats
+/// go through `tonemap`, float formats keep the linear radiance.
+pub fn write_image(path: &Path, film: &Film, tonemap: &ToneMapper) -> io::Result<()> {
+    write_rgb(
+        path,
+        film.width(),
+        film.height(),
+        || to_rgb8(&film.pixels(), tonemap),
+        || to_rgb32f(&film.pixels()),
+    )
+}
+
+/// Writes how many samples each pixel took. Float formats store the raw
+/// counts, 8-bit formats scale them so that `max_samples` is white.
+pub fn write_sample_map(path: &Path, film: &Film, max_samples: u32) -> io::Result<()> {
+    let counts = film.sample_counts();
+    write_rgb(
+        path,
+        film.width(),
+        film.height(),
+        || {
+            counts
+                .iter()
+                .flat_map(|&n| [(255.0 * n as f32 / max_samples as f32).round() as u8; 3])
+                .collect()
+        },
+        || counts.iter().flat_map(|&n| [n as f32; 3]).collect(),
+    )
+}
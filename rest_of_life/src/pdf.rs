@@ -1,32 +1,88 @@
 use crate::hittable::Hittable;
+use crate::microfacet;
+use crate::microfacet::{RoughInterface, GGX};
 use crate::onb::ONB;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 use std::f32;
 
-fn random_cosine_direction() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+fn random_cosine_direction(rng: &mut Sampler) -> Vector3<f32> {
     let r1 = rng.gen::<f32>();
     let r2 = rng.gen::<f32>();
     let z = (1.0 - r2).sqrt();
     let phi = 2.0 * f32::consts::PI * r1;
-    let x = phi.cos() * 2.0 * r2.sqrt();
-    let y = phi.sin() * 2.0 * r2.sqrt();
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
     Vector3::new(x, y, z)
 }
 
+/// Most lobes a material samples between.
+pub const MAX_LOBES: usize = 4;
+
+/// Sampling strategy of one lobe of a material, in a shading frame with the
+/// normal along +z, for light arriving from `wi`.
+#[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Lobe {
+    /// Cosine-weighted directions above the surface.
+    Cosine,
+    /// Reflection off GGX microfacets visible from `wi`, which is above.
+    GGX(GGX),
+    /// Reflection or transmission through a rough dielectric boundary.
+    Dielectric(RoughInterface),
+}
+
+impl Lobe {
+    pub fn value(&self, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        match self {
+            Lobe::Cosine => wo.z.max(0.0) / f32::consts::PI,
+            Lobe::GGX(ggx) => {
+                if wi.z <= 0.0 || wo.z <= 0.0 {
+                    return 0.0;
+                }
+                let h = (wi + wo).normalize();
+                ggx.pdf_visible(wi, &h) / (4.0 * wi.dot(&h))
+            }
+            Lobe::Dielectric(interface) => interface.pdf(wi, wo),
+        }
+    }
+
+    /// A direction, or `None` if the path ends.
+    pub fn generate(&self, wi: &Vector3<f32>, rng: &mut Sampler) -> Option<Vector3<f32>> {
+        match self {
+            Lobe::Cosine => Some(random_cosine_direction(rng)),
+            Lobe::GGX(ggx) => Some(microfacet::reflect(wi, &ggx.sample_visible(wi, rng))),
+            Lobe::Dielectric(interface) => interface.sample(wi, rng).map(|(wo, _)| wo),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub enum PDF<'a> {
     Cosine {
         uvw: ONB,
     },
+    /// Lobes picked with their weights, which sum to 1, in the frame `uvw`.
+    Lobes {
+        uvw: ONB,
+        wi: Vector3<f32>,
+        lobes: [(f32, Lobe); MAX_LOBES],
+    },
     Hittable {
         origin: Vector3<f32>,
-        hittable: &'a Box<dyn Hittable>,
+        hittable: &'a dyn Hittable,
     },
     Mixture {
         p: &'a PDF<'a>,
         q: &'a PDF<'a>,
     },
+    /// `p` or `q` picked with the probabilities 1 - `weight` and `weight`.
+    Blend {
+        p: Box<PDF<'a>>,
+        q: Box<PDF<'a>>,
+        weight: f32,
+    },
 }
 
 impl<'a> PDF<'a> {
@@ -36,7 +92,21 @@ impl<'a> PDF<'a> {
         }
     }
 
-    pub fn hittable(hittable: &'a Box<dyn Hittable>, origin: Vector3<f32>) -> Self {
+    /// Weighted lobes about the normal `w` of a surface seen from `wi`.
+    /// Unused entries have a weight of 0.
+    pub fn lobes(w: Vector3<f32>, wi: Vector3<f32>, lobes: [(f32, Lobe); MAX_LOBES]) -> Self {
+        let uvw = ONB::build_from_w(&w);
+        let wi = uvw.to_local(&wi.normalize());
+        PDF::Lobes { uvw, wi, lobes }
+    }
+
+    pub fn lobe(w: Vector3<f32>, wi: Vector3<f32>, lobe: Lobe) -> Self {
+        let mut lobes = [(0.0, Lobe::Cosine); MAX_LOBES];
+        lobes[0] = (1.0, lobe);
+        PDF::lobes(w, wi, lobes)
+    }
+
+    pub fn hittable(hittable: &'a dyn Hittable, origin: Vector3<f32>) -> Self {
         PDF::Hittable { origin, hittable }
     }
 
@@ -44,6 +114,14 @@ impl<'a> PDF<'a> {
         PDF::Mixture { p, q }
     }
 
+    pub fn blend(p: PDF<'a>, q: PDF<'a>, weight: f32) -> Self {
+        PDF::Blend {
+            p: Box::new(p),
+            q: Box::new(q),
+            weight,
+        }
+    }
+
     pub fn value(&self, direction: Vector3<f32>) -> f32 {
         match self {
             PDF::Cosine { uvw } => {
@@ -54,21 +132,55 @@ impl<'a> PDF<'a> {
                     1.0
                 }
             }
+            PDF::Lobes { uvw, wi, lobes } => {
+                let wo = uvw.to_local(&direction.normalize());
+                lobes
+                    .iter()
+                    .filter(|(weight, _)| *weight > 0.0)
+                    .map(|(weight, lobe)| weight * lobe.value(wi, &wo))
+                    .sum()
+            }
             PDF::Hittable { origin, hittable } => hittable.pdf_value(*origin, direction),
             PDF::Mixture { p, q } => 0.5 * p.value(direction) + 0.5 * q.value(direction),
+            PDF::Blend { p, q, weight } => {
+                (1.0 - weight) * p.value(direction) + weight * q.value(direction)
+            }
         }
     }
 
-    pub fn generate(&self) -> Vector3<f32> {
+    pub fn generate(&self, rng: &mut Sampler) -> Vector3<f32> {
         match self {
-            PDF::Cosine { uvw } => uvw.local(&random_cosine_direction()),
-            PDF::Hittable { origin, hittable } => hittable.random(*origin),
+            PDF::Cosine { uvw } => uvw.local(&random_cosine_direction(rng)),
+            PDF::Lobes { uvw, wi, lobes } => {
+                let mut pick = rng.gen::<f32>();
+                let lobe = lobes
+                    .iter()
+                    .find(|(weight, _)| {
+                        pick -= weight;
+                        pick < 0.0
+                    })
+                    .or_else(|| lobes.iter().rev().find(|(weight, _)| *weight > 0.0))
+                    .map(|(_, lobe)| lobe);
+                // A path that ends gets a direction along the surface, where
+                // nothing is scattered.
+                match lobe.and_then(|lobe| lobe.generate(wi, rng)) {
+                    Some(wo) => uvw.local(&wo),
+                    None => uvw.u(),
+                }
+            }
+            PDF::Hittable { origin, hittable } => hittable.random(*origin, rng),
             PDF::Mixture { p, q } => {
-                let mut rng = rand::thread_rng();
                 if rng.gen::<bool>() {
-                    p.generate()
+                    p.generate(rng)
+                } else {
+                    q.generate(rng)
+                }
+            }
+            PDF::Blend { p, q, weight } => {
+                if rng.gen::<f32>() < *weight {
+                    q.generate(rng)
                 } else {
-                    q.generate()
+                    p.generate(rng)
                 }
             }
         }
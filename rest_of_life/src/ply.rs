@@ -0,0 +1,479 @@
+use crate::material::{Lambertian, Material};
+use crate::texture::{ConstantTexture, VertexColorTexture};
+use crate::triangle::{Face, Mesh, MeshData};
+use nalgebra::Vector3;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::SplitAsciiWhitespace;
+use std::sync::Arc;
+
+type SharedMaterial = Arc<dyn Material + Send>;
+
+fn error(path: &Path, message: impl AsRef<str>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message.as_ref()),
+    )
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// Factor that maps a color channel of this type to [0, 1]. Integer
+    /// colors are taken to be 8 bit, except for 16-bit types.
+    fn color_scale(self) -> f32 {
+        match self {
+            Scalar::F32 | Scalar::F64 => 1.0,
+            Scalar::I16 | Scalar::U16 => 1.0 / 65535.0,
+            _ => 1.0 / 255.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<(String, Property)>,
+}
+
+impl Element {
+    fn find(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|(n, _)| n == name)
+    }
+}
+
+/// Element data following the header.
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8]),
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of file")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}'", token))
+            }
+            Body::Binary(bytes) => {
+                let size = scalar.size();
+                if bytes.len() < size {
+                    return Err("unexpected end of file".to_string());
+                }
+                let (value, rest) = bytes.split_at(size);
+                *bytes = rest;
+                Ok(match scalar {
+                    Scalar::I8 => value[0] as i8 as f64,
+                    Scalar::U8 => value[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes(value.try_into().unwrap()) as f64,
+                    Scalar::U16 => u16::from_le_bytes(value.try_into().unwrap()) as f64,
+                    Scalar::I32 => i32::from_le_bytes(value.try_into().unwrap()) as f64,
+                    Scalar::U32 => u32::from_le_bytes(value.try_into().unwrap()) as f64,
+                    Scalar::F32 => f32::from_le_bytes(value.try_into().unwrap()) as f64,
+                    Scalar::F64 => f64::from_le_bytes(value.try_into().unwrap()),
+                })
+            }
+        }
+    }
+
+    /// Reads one element into `values`, one entry per property: the value of
+    /// a scalar or the items of a list.
+    fn read_element(
+        &mut self,
+        element: &Element,
+        values: &mut Vec<Vec<f64>>,
+    ) -> Result<(), String> {
+        values.resize_with(element.properties.len(), Vec::new);
+        for ((_, property), value) in element.properties.iter().zip(values.iter_mut()) {
+            value.clear();
+            match *property {
+                Property::Scalar(scalar) => value.push(self.read(scalar)?),
+                Property::List { count, item } => {
+                    let count = self.read(count)?;
+                    if count < 0.0 {
+                        return Err(format!("invalid list length {}", count));
+                    }
+                    for _ in 0..count as usize {
+                        value.push(self.read(item)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits the file into its elements and the data after `end_header`.
+fn parse_header<'a>(path: &Path, bytes: &'a [u8]) -> io::Result<(Vec<Element>, Body<'a>)> {
+    let header_error = |line: usize, message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), line, message),
+        )
+    };
+    let mut elements: Vec<Element> = Vec::new();
+    let mut binary = None;
+    let mut rest = bytes;
+    let mut line = 0;
+    loop {
+        line += 1;
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| error(path, "missing 'end_header'"))?;
+        let text = std::str::from_utf8(&rest[..end])
+            .map_err(|_| header_error(line, "the header is not text".to_string()))?
+            .trim_end_matches('\r');
+        rest = &rest[end + 1..];
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if line == 1 {
+            if text != "ply" {
+                return Err(error(path, "not a PLY file"));
+            }
+            continue;
+        }
+        match fields.as_slice() {
+            ["format", "ascii", _] => binary = Some(false),
+            ["format", "binary_little_endian", _] => binary = Some(true),
+            ["format", format, ..] => {
+                return Err(header_error(
+                    line,
+                    format!("unsupported format '{}'", format),
+                ))
+            }
+            ["comment" | "obj_info", ..] | [] => {}
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| header_error(line, format!("invalid count '{}'", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            ["property", kind @ .., name] => {
+                let scalar = |name: &str| {
+                    Scalar::parse(name)
+                        .ok_or_else(|| header_error(line, format!("unknown type '{}'", name)))
+                };
+                let property = match kind {
+                    ["list", count, item] => Property::List {
+                        count: scalar(count)?,
+                        item: scalar(item)?,
+                    },
+                    [kind] => Property::Scalar(scalar(kind)?),
+                    _ => return Err(header_error(line, "invalid property".to_string())),
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push((name.to_string(), property)),
+                    None => return Err(header_error(line, "property before element".to_string())),
+                }
+            }
+            ["end_header"] => break,
+            _ => return Err(header_error(line, format!("unexpected '{}'", text))),
+        }
+    }
+    let body = match binary {
+        Some(true) => Body::Binary(rest),
+        Some(false) => Body::Ascii(
+            std::str::from_utf8(rest)
+                .map_err(|_| error(path, "the ASCII data is not text"))?
+                .split_ascii_whitespace(),
+        ),
+        None => return Err(error(path, "missing 'format'")),
+    };
+    Ok((elements, body))
+}
+
+/// Mesh read from a PLY file, before materials are assigned.
+struct Ply {
+    data: MeshData,
+    faces: Vec<Face>,
+    /// Vertex colors, empty if the vertices have none.
+    colors: Vec<Vector3<f32>>,
+}
+
+/// Parses the contents of a PLY file, with `path` naming it in errors.
+fn parse(path: &Path, bytes: &[u8]) -> io::Result<Ply> {
+    let (elements, mut body) = parse_header(path, bytes)?;
+    let mut data = MeshData::default();
+    let mut colors = Vec::new();
+    let mut faces = Vec::new();
+    let mut values = Vec::new();
+    for element in &elements {
+        let scalar = |name: &str| match element.find(name) {
+            Some(i) => match element.properties[i].1 {
+                Property::Scalar(scalar) => Some((i, scalar)),
+                Property::List { .. } => None,
+            },
+            None => None,
+        };
+        let vector = |names: [&str; 3]| -> Option<[(usize, Scalar); 3]> {
+            Some([scalar(names[0])?, scalar(names[1])?, scalar(names[2])?])
+        };
+        let mut read = |values: &mut Vec<Vec<f64>>| {
+            body.read_element(element, values)
+                .map_err(|e| error(path, format!("{} data: {}", element.name, e)))
+        };
+        match element.name.as_str() {
+            "vertex" => {
+                let position = vector(["x", "y", "z"])
+                    .ok_or_else(|| error(path, "vertices need x, y and z"))?;
+                let normal = vector(["nx", "ny", "nz"]);
+                let color = vector(["red", "green", "blue"]);
+                for _ in 0..element.count {
+                    read(&mut values)?;
+                    let get = |v: [(usize, Scalar); 3], scale: fn(Scalar) -> f32| {
+                        Vector3::from(v.map(|(i, s)| values[i][0] as f32 * scale(s)))
+                    };
+                    data.positions.push(get(position, |_| 1.0));
+                    if let Some(normal) = normal {
+                        data.normals.push(get(normal, |_| 1.0));
+                    }
+                    if let Some(color) = color {
+                        colors.push(get(color, Scalar::color_scale));
+                    }
+                }
+            }
+            "face" => {
+                let list = element
+                    .find("vertex_indices")
+                    .or_else(|| element.find("vertex_index"))
+                    .filter(|&i| matches!(element.properties[i].1, Property::List { .. }))
+                    .ok_or_else(|| error(path, "faces need a 'vertex_indices' list"))?;
+                for n in 0..element.count {
+                    read(&mut values)?;
+                    let indices = &values[list];
+                    if indices.len() < 3 {
+                        return Err(error(path, format!("face {} has fewer than 3 vertices", n)));
+                    }
+                    let mut corners = Vec::with_capacity(indices.len());
+                    for &i in indices {
+                        if i < 0.0 || i as usize >= data.positions.len() {
+                            return Err(error(
+                                path,
+                                format!("face {}: index {} out of range", n, i),
+                            ));
+                        }
+                        corners.push(i as usize);
+                    }
+                    let has_normals = !data.normals.is_empty();
+                    for k in 1..corners.len() - 1 {
+                        let c = [corners[0], corners[k], corners[k + 1]];
+                        faces.push(Face {
+                            positions: c,
+                            normals: if has_normals { Some(c) } else { None },
+                            uvs: None,
+                        });
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    read(&mut values)?;
+                }
+            }
+        }
+    }
+    if faces.is_empty() {
+        return Err(error(path, "the file has no faces"));
+    }
+    Ok(Ply {
+        data,
+        faces,
+        colors,
+    })
+}
+
+/// Loads a PLY file in ASCII or binary little-endian format. Vertices need
+/// `x`, `y` and `z` and may have normals (`nx`, `ny`, `nz`) and colors
+/// (`red`, `green`, `blue`); faces are lists named `vertex_indices` or
+/// `vertex_index`, split into triangle fans. Faces use `material` if given,
+/// otherwise a Lambertian textured with the vertex colors, and a grey
+/// Lambertian if there are none.
+pub fn load(path: &Path, material: Option<SharedMaterial>) -> io::Result<Mesh> {
+    let Ply {
+        data,
+        faces,
+        colors,
+    } = parse(path, &fs::read(path)?)?;
+    match material {
+        Some(material) => Ok(Mesh::new(
+            data,
+            faces.into_iter().map(|face| (face, material.clone())),
+        )),
+        None if !colors.is_empty() => {
+            let colors: Arc<[Vector3<f32>]> = colors.into();
+            Ok(Mesh::new(
+                data,
+                faces.into_iter().map(|face| {
+                    let texture = VertexColorTexture::new(colors.clone(), face.positions);
+                    (face, Lambertian::new(texture))
+                }),
+            ))
+        }
+        None => {
+            let grey = Arc::new(Lambertian::new(ConstantTexture::new(0.8, 0.8, 0.8)));
+            Ok(Mesh::new(
+                data,
+                faces.into_iter().map(|face| (face, grey.clone())),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+    /// Header of a quad with normals and colors, stored as `format`.
+    fn header(format: &str) -> String {
+        format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+             property float z\nproperty float nx\nproperty float ny\nproperty float nz\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+    }
+
+    fn ascii(indices: [i32; 4]) -> Vec<u8> {
+        let mut text = header("ascii");
+        for (p, c) in POSITIONS.iter().zip(COLORS) {
+            text += &format!(
+                "{} {} {} 0 0 1 {} {} {}\n",
+                p[0], p[1], p[2], c[0], c[1], c[2]
+            );
+        }
+        text += &format!(
+            "4 {} {} {} {}\n",
+            indices[0], indices[1], indices[2], indices[3]
+        );
+        text.into_bytes()
+    }
+
+    fn binary(indices: [i32; 4]) -> Vec<u8> {
+        let mut bytes = header("binary_little_endian").into_bytes();
+        for (p, c) in POSITIONS.iter().zip(COLORS) {
+            for x in p.iter().chain(&[0.0, 0.0, 1.0]) {
+                bytes.extend(x.to_le_bytes());
+            }
+            bytes.extend(c);
+        }
+        bytes.push(4);
+        for i in indices {
+            bytes.extend(i.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn parse_bytes(bytes: &[u8]) -> io::Result<Ply> {
+        parse(Path::new("quad.ply"), bytes)
+    }
+
+    fn parse_error(bytes: &[u8]) -> String {
+        match parse_bytes(bytes) {
+            Ok(_) => panic!("parsed an invalid file"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn check_quad(ply: Ply) {
+        let positions: Vec<_> = POSITIONS.iter().map(|&p| Vector3::from(p)).collect();
+        assert_eq!(ply.data.positions, positions);
+        assert_eq!(ply.data.normals, vec![Vector3::z(); 4]);
+        assert_eq!(ply.colors[0], Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(ply.colors[3], Vector3::new(1.0, 1.0, 1.0));
+        let faces: Vec<_> = ply.faces.iter().map(|f| (f.positions, f.normals)).collect();
+        assert_eq!(
+            faces,
+            [([0, 1, 2], Some([0, 1, 2])), ([0, 2, 3], Some([0, 2, 3]))]
+        );
+    }
+
+    #[test]
+    fn parses_ascii() {
+        check_quad(parse_bytes(&ascii([0, 1, 2, 3])).unwrap());
+    }
+
+    #[test]
+    fn parses_binary_little_endian() {
+        check_quad(parse_bytes(&binary([0, 1, 2, 3])).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = ascii([0, 1, 2, 3]);
+        let message = parse_error(&bytes[..bytes.len() - 3]);
+        assert!(message.contains("unexpected end of file"), "{}", message);
+        let bytes = binary([0, 1, 2, 3]);
+        let message = parse_error(&bytes[..bytes.len() - 2]);
+        assert!(message.contains("unexpected end of file"), "{}", message);
+        let message = parse_error(
+            header("ascii")
+                .split("end_header")
+                .next()
+                .unwrap()
+                .as_bytes(),
+        );
+        assert!(message.contains("missing 'end_header'"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let message = parse_error(&ascii([0, 1, 2, 4]));
+        assert!(message.contains("index 4 out of range"), "{}", message);
+        let message = parse_error(&binary([-1, 1, 2, 3]));
+        assert!(message.contains("index -1 out of range"), "{}", message);
+    }
+}
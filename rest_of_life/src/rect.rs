@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 use std::f32;
@@ -47,7 +48,7 @@ impl<M: Material> AARect<M> {
 }
 
 impl<M: Material> Hittable for AARect<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
         let t = (self.k - ray.origin()[k_axis]) / ray.direction()[k_axis];
         if t < t_min || t > t_max {
@@ -63,12 +64,19 @@ impl<M: Material> Hittable for AARect<M> {
                 let p = ray.point_at_parameter(t);
                 let mut normal = Vector3::zeros();
                 normal[k_axis] = 1.0;
+                let mut dpdu = Vector3::zeros();
+                dpdu[a_axis] = self.a1 - self.a0;
+                let mut dpdv = Vector3::zeros();
+                dpdv[b_axis] = self.b1 - self.b0;
                 Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
                 })
             }
@@ -76,8 +84,15 @@ impl<M: Material> Hittable for AARect<M> {
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        let min = Vector3::new(self.a0, self.b0, self.k - 0.0001);
-        let max = Vector3::new(self.a1, self.b1, self.k + 0.0001);
+        let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
+        let mut min = Vector3::zeros();
+        let mut max = Vector3::zeros();
+        min[a_axis] = self.a0;
+        max[a_axis] = self.a1;
+        min[b_axis] = self.b0;
+        max[b_axis] = self.b1;
+        min[k_axis] = self.k - 0.0001;
+        max[k_axis] = self.k + 0.0001;
         Some(AABB { min, max })
     }
 
@@ -96,8 +111,7 @@ impl<M: Material> Hittable for AARect<M> {
         }
     }
 
-    fn random(&self, o: Vector3<f32>) -> Vector3<f32> {
-        let mut rng = rand::thread_rng();
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
         let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
         let mut random_point = Vector3::zeros();
         random_point[a_axis] = rng.gen_range(self.a0..self.a1);
@@ -106,3 +120,96 @@ impl<M: Material> Hittable for AARect<M> {
         random_point - o
     }
 }
+
+/// Parallelogram spanned by the edges `u` and `v` from `corner`, facing
+/// along u × v. The texture coordinates run along the two edges.
+#[derive(Clone)]
+pub struct Quad<M: Material> {
+    corner: Vector3<f32>,
+    u: Vector3<f32>,
+    v: Vector3<f32>,
+    normal: Vector3<f32>,
+    // Maps a point in the plane, relative to the corner, to its coordinates
+    // along the edges.
+    w: Vector3<f32>,
+    area: f32,
+    material: M,
+}
+
+impl<M: Material> Quad<M> {
+    pub fn new(corner: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, material: M) -> Self {
+        let n = u.cross(&v);
+        Quad {
+            corner,
+            u,
+            v,
+            normal: n.normalize(),
+            w: n / n.norm_squared(),
+            area: n.norm(),
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Quad<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(&ray.direction());
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(&(self.corner - ray.origin())) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let p = ray.point_at_parameter(t);
+        let planar = p - self.corner;
+        let a = self.w.dot(&planar.cross(&self.v));
+        let b = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+        Some(HitRecord {
+            t,
+            u: a,
+            v: b,
+            p,
+            normal: self.normal,
+            shading_normal: self.normal,
+            dpdu: self.u,
+            dpdv: self.v,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        let corners = [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        let min = corners.iter().fold(corners[0], |m, c| m.inf(c));
+        let max = corners.iter().fold(corners[0], |m, c| m.sup(c));
+        // Pad the box so it has some thickness when the quad is axis-aligned.
+        let pad = Vector3::repeat(0.0001);
+        Some(AABB::new(min - pad, max + pad))
+    }
+
+    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
+        if let Some(hit) = self.hit(&Ray::new(o, v, 0.0), 0.001, f32::MAX) {
+            let distance_squared = hit.t.powi(2) * v.norm_squared();
+            let cosine = v.dot(&hit.normal).abs() / v.norm();
+            if cosine != 0.0 {
+                distance_squared / (cosine * self.area)
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        self.corner + rng.gen::<f32>() * self.u + rng.gen::<f32>() * self.v - o
+    }
+}
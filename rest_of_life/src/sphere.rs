@@ -3,6 +3,7 @@ use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::onb::ONB;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 use std::f32;
@@ -15,11 +16,33 @@ fn get_sphere_uv(p: &Vector3<f32>) -> (f32, f32) {
     (u, v)
 }
 
-fn random_to_sphere(radius: f32, distance_squared: f32) -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+/// Derivatives of the point at `p` on a unit sphere scaled by `radius` by
+/// the uvs of `get_sphere_uv`. They vanish at the poles.
+fn get_sphere_tangents(p: &Vector3<f32>, radius: f32) -> (Vector3<f32>, Vector3<f32>) {
+    let dpdu = 2.0 * f32::consts::PI * radius * Vector3::new(p.z, 0.0, -p.x);
+    let rho = (p.x * p.x + p.z * p.z).sqrt();
+    if rho == 0.0 {
+        return (dpdu, Vector3::zeros());
+    }
+    let dpdv = f32::consts::PI * radius * Vector3::new(-p.y * p.x / rho, rho, -p.y * p.z / rho);
+    (dpdu, dpdv)
+}
+
+/// Cosine of the half angle of the cone of directions from `o` towards the
+/// sphere, or -1 if the sphere surrounds `o` and every direction hits it.
+fn cos_theta_max(center: Vector3<f32>, radius: f32, o: Vector3<f32>) -> f32 {
+    let distance_squared = (center - o).norm_squared();
+    if distance_squared > radius.powi(2) {
+        (1.0 - radius.powi(2) / distance_squared).sqrt()
+    } else {
+        -1.0
+    }
+}
+
+fn random_to_sphere(cos_theta_max: f32, rng: &mut Sampler) -> Vector3<f32> {
     let r1 = rng.gen::<f32>();
     let r2 = rng.gen::<f32>();
-    let z = 1.0 + r2 * ((1.0 - radius.powi(2) / distance_squared).sqrt() - 1.0);
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
     let phi = 2.0 * f32::consts::PI * r1;
     let x = phi.cos() * (1.0 - z.powi(2)).sqrt();
     let y = phi.sin() * (1.0 - z.powi(2)).sqrt();
@@ -44,7 +67,7 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let oc = ray.origin() - self.center;
         let a = ray.direction().dot(&ray.direction());
         let b = oc.dot(&ray.direction());
@@ -57,12 +80,16 @@ impl<M: Material> Hittable for Sphere<M> {
                 let p = ray.point_at_parameter(t);
                 let normal = (p - self.center) / self.radius;
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
                 });
             }
@@ -71,12 +98,16 @@ impl<M: Material> Hittable for Sphere<M> {
                 let p = ray.point_at_parameter(t);
                 let normal = (p - self.center) / self.radius;
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_tangents(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
                     normal,
+                    shading_normal: normal,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
                 });
             }
@@ -93,8 +124,7 @@ impl<M: Material> Hittable for Sphere<M> {
 
     fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
         if let Some(_hit) = self.hit(&Ray::new(o, v, 0.0), 0.001, f32::MAX) {
-            let cos_theta_max =
-                (1.0 - self.radius.powi(2) / (self.center - o).norm_squared()).sqrt();
+            let cos_theta_max = cos_theta_max(self.center, self.radius, o);
             let solid_angle = 2.0 * f32::consts::PI * (1.0 - cos_theta_max);
             1.0 / solid_angle
         } else {
@@ -102,10 +132,9 @@ impl<M: Material> Hittable for Sphere<M> {
         }
     }
 
-    fn random(&self, o: Vector3<f32>) -> Vector3<f32> {
-        let direction = self.center - o;
-        let distance_squared = direction.norm_squared();
-        let uvw = ONB::build_from_w(&direction);
-        uvw.local(&random_to_sphere(self.radius, distance_squared))
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        let uvw = ONB::build_from_w(&(self.center - o));
+        let cos_theta_max = cos_theta_max(self.center, self.radius, o);
+        uvw.local(&random_to_sphere(cos_theta_max, rng))
     }
 }
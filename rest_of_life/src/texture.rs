@@ -1,9 +1,16 @@
 use nalgebra::Vector3;
+use std::sync::Arc;
 
 pub trait Texture: Sync {
     fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32>;
 }
 
+impl<T: Texture + Send + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        (**self).value(u, v, p)
+    }
+}
+
 #[derive(Clone)]
 pub struct ConstantTexture {
     color: Vector3<f32>,
@@ -22,3 +29,59 @@ impl Texture for ConstantTexture {
         self.color
     }
 }
+
+#[derive(Clone)]
+pub struct ImageTexture {
+    data: Vec<u8>,
+    nx: u32,
+    ny: u32,
+}
+
+impl ImageTexture {
+    pub fn new(data: Vec<u8>, nx: u32, ny: u32) -> Self {
+        ImageTexture { data, nx, ny }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
+        let nx = self.nx as usize;
+        let ny = self.ny as usize;
+        let mut i = (u * nx as f32) as usize;
+        let mut j = ((1.0 - v) * ny as f32) as usize;
+        if i > nx - 1 {
+            i = nx - 1
+        }
+        if j > ny - 1 {
+            j = ny - 1
+        }
+        let idx = 3 * i + 3 * nx * j;
+        let r = self.data[idx] as f32 / 255.0;
+        let g = self.data[idx + 1] as f32 / 255.0;
+        let b = self.data[idx + 2] as f32 / 255.0;
+        Vector3::new(r, g, b)
+    }
+}
+
+/// Colors given at the vertices of a mesh, shared by its triangles. Each
+/// triangle blends the colors of its own corners with the barycentric
+/// coordinates it reports as uvs when it has no texture coordinates.
+#[derive(Clone)]
+pub struct VertexColorTexture {
+    colors: Arc<[Vector3<f32>]>,
+    corners: [usize; 3],
+}
+
+impl VertexColorTexture {
+    /// The texture of the triangle with `corners` among the vertex `colors`.
+    pub fn new(colors: Arc<[Vector3<f32>]>, corners: [usize; 3]) -> Self {
+        VertexColorTexture { colors, corners }
+    }
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, u: f32, v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
+        let [c0, c1, c2] = self.corners.map(|i| self.colors[i]);
+        (1.0 - u - v) * c0 + u * c1 + v * c2
+    }
+}
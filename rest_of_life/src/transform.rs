@@ -0,0 +1,125 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use nalgebra::{Isometry3, Matrix4, Point3, Unit, UnitQuaternion, Vector3};
+use std::f32;
+
+/// Places an object with an affine matrix. Rays are taken into object space
+/// with the inverse, and normals back to world space with its transpose.
+pub struct Transform<H: Hittable> {
+    matrix: Matrix4<f32>,
+    inverse: Matrix4<f32>,
+    hittable: H,
+}
+
+#[allow(dead_code)]
+impl<H: Hittable> Transform<H> {
+    /// Panics if `matrix` cannot be inverted.
+    pub fn new(hittable: H, matrix: Matrix4<f32>) -> Self {
+        let inverse = matrix
+            .try_inverse()
+            .expect("transform matrix is not invertible");
+        Transform {
+            matrix,
+            inverse,
+            hittable,
+        }
+    }
+
+    pub fn translate(hittable: H, offset: Vector3<f32>) -> Self {
+        Transform::new(hittable, Matrix4::new_translation(&offset))
+    }
+
+    pub fn scale(hittable: H, factors: Vector3<f32>) -> Self {
+        Transform::new(hittable, Matrix4::new_nonuniform_scaling(&factors))
+    }
+
+    /// Rotates by `angle` degrees about `axis`, counterclockwise when the axis
+    /// points at the viewer.
+    pub fn rotate(hittable: H, axis: Vector3<f32>, angle: f32) -> Self {
+        let rotation =
+            UnitQuaternion::from_axis_angle(&Unit::new_normalize(axis), angle.to_radians());
+        Transform::quaternion(hittable, rotation)
+    }
+
+    pub fn quaternion(hittable: H, rotation: UnitQuaternion<f32>) -> Self {
+        Transform::new(hittable, rotation.to_homogeneous())
+    }
+
+    /// Moves the object's origin to `from` and turns its z axis towards `at`,
+    /// keeping its y axis as close to `up` as possible.
+    pub fn look_at(hittable: H, from: Vector3<f32>, at: Vector3<f32>, up: Vector3<f32>) -> Self {
+        let isometry = Isometry3::face_towards(&Point3::from(from), &Point3::from(at), &up);
+        Transform::new(hittable, isometry.to_homogeneous())
+    }
+
+    /// Applies `matrix` after the current transform. Composing matrices this
+    /// way costs one ray transform per hit test, where nesting `Transform`s
+    /// costs one per level and loosens the bounding box at each of them.
+    pub fn then(self, matrix: Matrix4<f32>) -> Self {
+        Transform::new(self.hittable, matrix * self.matrix)
+    }
+}
+
+impl<H: Hittable> Hittable for Transform<H> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // The direction is not renormalized, so distances along the ray keep
+        // their meaning in object space.
+        let origin = self
+            .inverse
+            .transform_point(&Point3::from(ray.origin()))
+            .coords;
+        let direction = self.inverse.transform_vector(&ray.direction());
+        let local_ray = Ray::new(origin, direction, ray.time());
+        self.hittable.hit(&local_ray, t_min, t_max).map(|mut hit| {
+            hit.p = self.matrix.transform_point(&Point3::from(hit.p)).coords;
+            hit.dpdu = self.matrix.transform_vector(&hit.dpdu);
+            hit.dpdv = self.matrix.transform_vector(&hit.dpdv);
+            let to_world = |normal: &Vector3<f32>| {
+                self.inverse
+                    .fixed_slice::<3, 3>(0, 0)
+                    .tr_mul(normal)
+                    .normalize()
+            };
+            hit.normal = to_world(&hit.normal);
+            hit.shading_normal = to_world(&hit.shading_normal);
+            hit
+        })
+    }
+
+    /// Bounds the transformed box of the object (Arvo 1990), which is exact
+    /// for the box itself.
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1).map(|bbox| {
+            let mut min: Vector3<f32> = self.matrix.fixed_slice::<3, 1>(0, 3).into();
+            let mut max = min;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let a = self.matrix[(i, j)] * bbox.min[j];
+                    let b = self.matrix[(i, j)] * bbox.max[j];
+                    min[i] += a.min(b);
+                    max[i] += a.max(b);
+                }
+            }
+            AABB::new(min, max)
+        })
+    }
+
+    /// The object's density for the direction `v` taken into object space,
+    /// times the Jacobian |det L| / |L v|^3 of that change of solid angle for
+    /// the linear part L of the inverse and a unit `v`.
+    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
+        let origin = self.inverse.transform_point(&Point3::from(o)).coords;
+        let direction = self.inverse.transform_vector(&v);
+        let linear = self.inverse.fixed_slice::<3, 3>(0, 0);
+        let stretch = direction.norm() / v.norm();
+        self.hittable.pdf_value(origin, direction) * linear.determinant().abs() / stretch.powi(3)
+    }
+
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        let origin = self.inverse.transform_point(&Point3::from(o)).coords;
+        self.matrix
+            .transform_vector(&self.hittable.random(origin, rng))
+    }
+}
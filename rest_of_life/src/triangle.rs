@@ -0,0 +1,229 @@
+use crate::aabb::AABB;
+use crate::bvh::BVH;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use nalgebra::{Vector2, Vector3};
+use rand::Rng;
+use std::sync::Arc;
+
+/// Vertex attributes shared by the triangles of a mesh.
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<Vector2<f32>>,
+}
+
+/// Indices of a triangle's corners into the position, normal and uv arrays
+/// of its `MeshData`. Normals and uvs are optional.
+#[derive(Clone, Copy)]
+pub struct Face {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+/// Watertight ray/triangle test (Woop, Benthin and Wald 2013). Returns the ray
+/// parameter and the barycentric weights of `p0`, `p1` and `p2`.
+fn intersect(ray: &Ray, p: [Vector3<f32>; 3], t_min: f32, t_max: f32) -> Option<(f32, [f32; 3])> {
+    let d = ray.direction();
+    let kz = d.abs().imax();
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    if d[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let (sx, sy, sz) = (d[kx] / d[kz], d[ky] / d[kz], 1.0 / d[kz]);
+    let a = p[0] - ray.origin();
+    let b = p[1] - ray.origin();
+    let c = p[2] - ray.origin();
+    let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+    let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+    let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        // Redo the edge tests in double precision when the ray passes exactly
+        // through an edge, so that neighbouring triangles agree on the hit.
+        u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+        v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+        w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+    }
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+    let t = (u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz]) / det;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, [u / det, v / det, w / det]))
+}
+
+pub struct Triangle<M: Material> {
+    mesh: Arc<MeshData>,
+    face: Face,
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    pub fn new(mesh: Arc<MeshData>, face: Face, material: M) -> Self {
+        Triangle {
+            mesh,
+            face,
+            material,
+        }
+    }
+
+    fn positions(&self) -> [Vector3<f32>; 3] {
+        self.face.positions.map(|i| self.mesh.positions[i])
+    }
+
+    /// Derivatives of the position `p` by the texture coordinates, which are
+    /// the barycentrics of the second and third corner without uvs.
+    fn tangents(&self, p: &[Vector3<f32>; 3]) -> (Vector3<f32>, Vector3<f32>) {
+        let uv = match self.face.uvs {
+            Some(uv) => uv.map(|i| self.mesh.uvs[i]),
+            None => [Vector2::zeros(), Vector2::x(), Vector2::y()],
+        };
+        let (dp1, dp2) = (p[1] - p[0], p[2] - p[0]);
+        let (duv1, duv2) = (uv[1] - uv[0], uv[2] - uv[0]);
+        let det = duv1.x * duv2.y - duv1.y * duv2.x;
+        if det.abs() < 1e-12 {
+            // Without usable uvs any two edges span the plane.
+            return (dp1, dp2);
+        }
+        (
+            (duv2.y * dp1 - duv1.y * dp2) / det,
+            (duv1.x * dp2 - duv2.x * dp1) / det,
+        )
+    }
+
+    fn geometric_normal(&self) -> Vector3<f32> {
+        let [p0, p1, p2] = self.positions();
+        (p1 - p0).cross(&(p2 - p0))
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let p = self.positions();
+        let (t, b) = intersect(ray, p, t_min, t_max)?;
+        let mut normal = self.geometric_normal().normalize();
+        let mut shading_normal = match self.face.normals {
+            Some(n) => (0..3)
+                .map(|i| b[i] * self.mesh.normals[n[i]])
+                .sum::<Vector3<f32>>()
+                .normalize(),
+            None => normal,
+        };
+        // The winding may disagree with the vertex normals, which decide
+        // which side is the outside.
+        if normal.dot(&shading_normal) < 0.0 {
+            normal = -normal;
+        }
+        // Near the silhouette the ray can see the shading normal from the
+        // other side than the surface; shade with the surface there.
+        if normal.dot(&ray.direction()) * shading_normal.dot(&ray.direction()) <= 0.0 {
+            shading_normal = normal;
+        }
+        // Without texture coordinates the barycentrics serve as uvs.
+        let uv = match self.face.uvs {
+            Some(uv) => (0..3).map(|i| b[i] * self.mesh.uvs[uv[i]]).sum(),
+            None => Vector2::new(b[1], b[2]),
+        };
+        let (dpdu, dpdv) = self.tangents(&p);
+        Some(HitRecord {
+            t,
+            u: uv.x,
+            v: uv.y,
+            p: b[0] * p[0] + b[1] * p[1] + b[2] * p[2],
+            normal,
+            shading_normal,
+            dpdu,
+            dpdv,
+            material: &self.material,
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        let [p0, p1, p2] = self.positions();
+        let pad = Vector3::repeat(0.0001);
+        Some(AABB::new(
+            p0.inf(&p1).inf(&p2) - pad,
+            p0.sup(&p1).sup(&p2) + pad,
+        ))
+    }
+
+    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
+        match intersect(&Ray::new(o, v, 0.0), self.positions(), 0.001, f32::MAX) {
+            Some((t, _)) => {
+                let normal = self.geometric_normal();
+                let area = 0.5 * normal.norm();
+                let distance_squared = t.powi(2) * v.norm_squared();
+                let cosine = v.dot(&normal).abs() / (v.norm() * normal.norm());
+                if cosine != 0.0 {
+                    distance_squared / (cosine * area)
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        let [p0, p1, p2] = self.positions();
+        let su = rng.gen::<f32>().sqrt();
+        let (b0, b1) = (1.0 - su, rng.gen::<f32>() * su);
+        b0 * p0 + b1 * p1 + (1.0 - b0 - b1) * p2 - o
+    }
+}
+
+/// Triangle mesh with its own BVH. As a light it samples every triangle with
+/// equal probability.
+pub struct Mesh {
+    bvh: BVH,
+}
+
+impl Mesh {
+    /// Builds a mesh from `faces`, each paired with its material.
+    pub fn new<M: Material + 'static>(
+        data: MeshData,
+        faces: impl IntoIterator<Item = (Face, M)>,
+    ) -> Self {
+        let data = Arc::new(data);
+        let triangles: Vec<Box<dyn Hittable>> = faces
+            .into_iter()
+            .map(|(face, material)| {
+                Box::new(Triangle::new(data.clone(), face, material)) as Box<dyn Hittable>
+            })
+            .collect();
+        Mesh {
+            bvh: BVH::new(triangles, 0.0, 1.0),
+        }
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.bvh.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
+        self.bvh.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<f32>, rng: &mut Sampler) -> Vector3<f32> {
+        self.bvh.random(o, rng)
+    }
+}
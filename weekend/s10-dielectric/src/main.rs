@@ -9,7 +9,7 @@ use s10_dielectric::{
     vec3::{unit_vector, Color, Point3, Vec3},
 };
 use std::io::{self, Write};
-use std::{fs::File, rc::Rc};
+use std::{env, fs::File, rc::Rc};
 
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
 const IMAGE_WIDTH: i32 = 384;
@@ -17,8 +17,6 @@ const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
 const SAMPLES_PER_PIXEL: i32 = 100;
 const MAX_DEPTH: i32 = 50;
 
-const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
-
 fn ray_color(r: Ray, world: &Vec<Hittable>, depth: i32) -> Color {
     let mut rec = HitRecord {
         p: Point3::new(0.0, 0.0, 0.0),
@@ -71,7 +69,7 @@ fn ray_color(r: Ray, world: &Vec<Hittable>, depth: i32) -> Color {
 }
 
 fn main() -> io::Result<()> {
-    let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
+    let mut out = format!("P6\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT).into_bytes();
 
     let material_ground = Hittable::new(
         Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0),
@@ -104,9 +102,6 @@ fn main() -> io::Result<()> {
 
     let cam = Camera::new();
 
-    let mut data_vector = vec![String::from(""); COUNT_MAX];
-    let mut index = 0;
-
     for j in (0..IMAGE_HEIGHT).rev() {
         for i in 0..IMAGE_WIDTH {
             let mut pixel_color = Color::new(0.0, 0.0, 0.0);
@@ -116,14 +111,13 @@ fn main() -> io::Result<()> {
                 let r = cam.get_ray(u, v);
                 pixel_color += ray_color(r, &world, MAX_DEPTH);
             }
-            data_vector[index] = write_color(pixel_color, SAMPLES_PER_PIXEL);
-            index += 1;
+            write_color(&mut out, pixel_color, SAMPLES_PER_PIXEL);
         }
     }
 
-    out_str += &data_vector.join("\n");
-
-    let mut file = File::create("a.ppm").unwrap();
-    file.write_fmt(format_args!("{}", out_str))?;
+    // Binary PPM, to the path given as the first argument.
+    let path = env::args().nth(1).unwrap_or_else(|| "a.ppm".to_string());
+    let mut file = File::create(path)?;
+    file.write_all(&out)?;
     Ok(())
 }
@@ -1,15 +1,12 @@
+use std::env;
 use std::fs::File;
 use std::io::{self, stdout, Write};
 
 const IMAGE_WIDTH: i32 = 256;
 const IMAGE_HEIGHT: i32 = 256;
-const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
 
 fn main() -> io::Result<()> {
-    let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
-
-    let mut data_vector = vec![String::from(""); COUNT_MAX];
-    let mut index = 0;
+    let mut out = format!("P6\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT).into_bytes();
 
     for j in (0..IMAGE_HEIGHT).rev() {
         println!("Progress: {} / {}    \r", j, IMAGE_HEIGHT);
@@ -24,17 +21,18 @@ fn main() -> io::Result<()> {
             let ig = (A * g) as u8;
             let ib = (A * b) as u8;
 
-            data_vector[index] = format!("{} {} {}", ir, ig, ib);
-            index += 1;
+            out.extend_from_slice(&[ir, ig, ib]);
         }
     }
 
     println!("Writing to file...");
 
-    out_str += &data_vector.join("\n");
-
-    let mut file = File::create("2-output-an-image.ppm").unwrap();
-    file.write_fmt(format_args!("{}", out_str))?;
+    // Binary PPM, to the path given as the first argument.
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "2-output-an-image.ppm".to_string());
+    let mut file = File::create(path)?;
+    file.write_all(&out)?;
     println!("Done!");
     Ok(())
 }
@@ -1,11 +1,10 @@
 use crate::vec3::Color;
 
-pub fn write_color(pixel_color: Color) -> String {
+pub fn write_color(out: &mut Vec<u8>, pixel_color: Color) {
     const A: f64 = 255.999;
-    format!(
-        "{} {} {}",
+    out.extend_from_slice(&[
         (A * pixel_color.x()) as u8,
         (A * pixel_color.y()) as u8,
         (A * pixel_color.z()) as u8,
-    )
+    ]);
 }
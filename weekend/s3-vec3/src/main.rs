@@ -1,16 +1,13 @@
 use s3_vec3::{color::write_color, vec3::Color};
+use std::env;
 use std::fs::File;
 use std::io::{self, stdout, Write};
 
 const IMAGE_WIDTH: i32 = 256;
 const IMAGE_HEIGHT: i32 = 256;
-const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
 
 fn main() -> io::Result<()> {
-    let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
-
-    let mut data_vector = vec![String::from(""); COUNT_MAX];
-    let mut index = 0;
+    let mut out = format!("P6\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT).into_bytes();
 
     for j in (0..IMAGE_HEIGHT).rev() {
         for i in 0..IMAGE_WIDTH {
@@ -19,14 +16,15 @@ fn main() -> io::Result<()> {
                 j as f64 / (IMAGE_HEIGHT - 1) as f64,
                 0.25,
             );
-            data_vector[index] = write_color(pixel_color);
-            index += 1;
+            write_color(&mut out, pixel_color);
         }
     }
 
-    out_str += &data_vector.join("\n");
-
-    let mut file = File::create("3-vec3.ppm").unwrap();
-    file.write_fmt(format_args!("{}", out_str))?;
+    // Binary PPM, to the path given as the first argument.
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "3-vec3.ppm".to_string());
+    let mut file = File::create(path)?;
+    file.write_all(&out)?;
     Ok(())
 }
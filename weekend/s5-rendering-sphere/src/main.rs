@@ -3,6 +3,7 @@ use s5_rendering_sphere::{
     ray::Ray,
     vec3::{dot, unit_vector, Color, Point3, Vec3},
 };
+use std::env;
 use std::fs::File;
 use std::io::{self, Write};
 
@@ -13,8 +14,6 @@ const VIEWPORT_HEIGHT: f64 = 2.0;
 const VIEWPORT_WIDTH: f64 = ASPECT_RATIO * VIEWPORT_HEIGHT;
 const FOCAL_LENGTH: f64 = 1.0;
 
-const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
-
 fn hit_sphere(center: Point3, radius: f64, r: Ray) -> bool {
     let oc = r.origin() - center;
     let a = dot(r.direction(), r.direction());
@@ -35,7 +34,7 @@ fn ray_color(r: Ray) -> Color {
 }
 
 fn main() -> io::Result<()> {
-    let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
+    let mut out = format!("P6\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT).into_bytes();
 
     let origin = Point3::new(0.0, 0.0, 0.0);
     let horizontal = Vec3::new(VIEWPORT_WIDTH, 0.0, 0.0);
@@ -43,9 +42,6 @@ fn main() -> io::Result<()> {
     let lower_left_corner =
         origin - horizontal / 2.0 - vertical / 2.0 - Vec3::new(0.0, 0.0, FOCAL_LENGTH);
 
-    let mut data_vector = vec![String::from(""); COUNT_MAX];
-    let mut index = 0;
-
     for j in (0..IMAGE_HEIGHT).rev() {
         for i in 0..IMAGE_WIDTH {
             let u = i as f64 / (IMAGE_WIDTH - 1) as f64;
@@ -55,14 +51,15 @@ fn main() -> io::Result<()> {
                 lower_left_corner + u * horizontal + v * vertical - origin,
             );
             let pixel_color = ray_color(r);
-            data_vector[index] = write_color(pixel_color);
-            index += 1;
+            write_color(&mut out, pixel_color);
         }
     }
 
-    out_str += &data_vector.join("\n");
-
-    let mut file = File::create("5-rendering-sphere.ppm").unwrap();
-    file.write_fmt(format_args!("{}", out_str))?;
+    // Binary PPM, to the path given as the first argument.
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "5-rendering-sphere.ppm".to_string());
+    let mut file = File::create(path)?;
+    file.write_all(&out)?;
     Ok(())
 }
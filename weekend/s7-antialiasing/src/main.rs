@@ -7,6 +7,7 @@ use s7_antialiasing::{
     sphere::Sphere,
     hittable::{HitRecord, Hittable, Shape},
 };
+use std::env;
 use std::fs::File;
 use std::io::{self, Write};
 
@@ -15,8 +16,6 @@ const IMAGE_WIDTH: i32 = 384;
 const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
 const SAMPLES_PER_PIXEL: i32 = 100;
 
-const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
-
 fn ray_color(r: Ray, world: &Vec<Hittable<Sphere>>) -> Color {
     let mut rec = HitRecord {
         p: Point3::new(0.0, 0.0, 0.0),
@@ -35,7 +34,7 @@ fn ray_color(r: Ray, world: &Vec<Hittable<Sphere>>) -> Color {
 }
 
 fn main() -> io::Result<()> {
-    let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
+    let mut out = format!("P6\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT).into_bytes();
 
     let world = vec![
         Hittable::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)),
@@ -44,9 +43,6 @@ fn main() -> io::Result<()> {
 
     let cam = Camera::new();
 
-    let mut data_vector = vec![String::from(""); COUNT_MAX];
-    let mut index = 0;
-
     for j in (0..IMAGE_HEIGHT).rev() {
         for i in 0..IMAGE_WIDTH {
             let mut pixel_color = Color::new(0.0, 0.0, 0.0);
@@ -56,14 +52,13 @@ fn main() -> io::Result<()> {
                 let r = cam.get_ray(u, v);
                 pixel_color += ray_color(r, &world);
             }
-            data_vector[index] = write_color(pixel_color, SAMPLES_PER_PIXEL);
-            index += 1;
+            write_color(&mut out, pixel_color, SAMPLES_PER_PIXEL);
         }
     }
 
-    out_str += &data_vector.join("\n");
-
-    let mut file = File::create("a.ppm").unwrap();
-    file.write_fmt(format_args!("{}", out_str))?;
+    // Binary PPM, to the path given as the first argument.
+    let path = env::args().nth(1).unwrap_or_else(|| "a.ppm".to_string());
+    let mut file = File::create(path)?;
+    file.write_all(&out)?;
     Ok(())
 }
@@ -1,7 +1,7 @@
 use crate::vec3::Color;
 use crate::util::clamp;
 
-pub fn write_color(pixel_color: Color, samples_per_pixel: i32) -> String {
+pub fn write_color(out: &mut Vec<u8>, pixel_color: Color, samples_per_pixel: i32) {
     let mut r = pixel_color.x();
     let mut g = pixel_color.y();
     let mut b = pixel_color.z();
@@ -10,10 +10,9 @@ pub fn write_color(pixel_color: Color, samples_per_pixel: i32) -> String {
     g = (scale * g).sqrt();
     b = (scale * b).sqrt();
     const A: f64 = 256.0;
-    format!(
-        "{} {} {}",
+    out.extend_from_slice(&[
         (A * clamp(r, 0.0, 0.999)) as u8,
         (A * clamp(g, 0.0, 0.999)) as u8,
         (A * clamp(b, 0.0, 0.999)) as u8,
-    )
+    ]);
 }